@@ -1,7 +1,7 @@
-use crate::helix::Client;
+use crate::error::{Error, Result};
 use crate::helix::User;
+use crate::helix::{Client, ClientBuilder};
 
-use anyhow::Result;
 use async_trait::async_trait;
 use chrono::DateTime;
 use chrono::Utc;
@@ -10,7 +10,7 @@ use serde::Serialize;
 
 #[async_trait]
 pub trait TokenStorage {
-    async fn save(&mut self, token: &Token) -> Result<()>;
+    async fn save(&mut self, token: &Token) -> anyhow::Result<()>;
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -42,7 +42,7 @@ pub struct Token {
 pub struct VoidStorage {}
 #[async_trait]
 impl TokenStorage for VoidStorage {
-    async fn save(&mut self, _token: &Token) -> Result<()> {
+    async fn save(&mut self, _token: &Token) -> anyhow::Result<()> {
         Ok(())
     }
 }
@@ -50,48 +50,85 @@ impl TokenStorage for VoidStorage {
 #[derive(Deserialize)]
 struct ValidateToken {
     pub expires_in: i64,
+    #[serde(default)]
+    pub scopes: Vec<String>,
 }
 
 impl<T: TokenStorage> Client<T> {
-    pub async fn validate_token(&mut self) -> Result<()> {
+    pub async fn validate_token(&self) -> Result<()> {
         let token = match self
-            .get::<ValidateToken>("https://id.twitch.tv/oauth2/validate".to_string())
+            .get::<ValidateToken>(format!("{0}/validate", self.oauth_url))
             .await
         {
             Ok(r) => r,
             Err(..) => {
-                self.refresh_token().await?;
-                return Ok(());
+                return self
+                    .refresh_token()
+                    .await
+                    .map_err(|e| Error::TokenRefresh(Box::new(e)));
             }
         };
 
         if token.expires_in < 3600 {
-            self.refresh_token().await?;
+            self.refresh_token()
+                .await
+                .map_err(|e| Error::TokenRefresh(Box::new(e)))?;
         }
 
         Ok(())
     }
 
-    pub async fn refresh_token(&mut self) -> Result<()> {
-        if self.token.token_type == TokenType::AppAccessToken {
-            self.get_app_token().await?;
+    pub async fn get_token_scopes(&self) -> Result<Vec<String>> {
+        let token = self
+            .get::<ValidateToken>(format!("{0}/validate", self.oauth_url))
+            .await?;
+        Ok(token.scopes)
+    }
+
+    pub async fn refresh_token(&self) -> Result<()> {
+        let mut token_storage = self.token_storage.lock().await;
+        self.refresh_token_locked(&mut token_storage).await
+    }
+
+    pub(crate) async fn refresh_expired_token(&self, access_token: &str) -> Result<()> {
+        let mut token_storage = self.token_storage.lock().await;
+        if self.token.read().await.access_token != access_token {
+            //Another task already refreshed the token while we were waiting for the lock.
             return Ok(());
         }
+        self.refresh_token_locked(&mut token_storage).await
+    }
+
+    async fn refresh_token_locked(&self, token_storage: &mut T) -> Result<()> {
+        let (token_type, refresh_token) = {
+            let token = self.token.read().await;
+            (token.token_type.clone(), token.refresh_token.clone())
+        };
+
+        if token_type == TokenType::AppAccessToken {
+            return self.get_app_token(token_storage).await;
+        }
 
         let res = self
             .http_request::<()>(
                 reqwest::Method::POST,
-                "https://id.twitch.tv/oauth2/token".to_string(),
+                format!("{0}/token", self.oauth_url),
                 None,
                 Some(format!(
                     "client_id={0}&client_secret={1}&grant_type=refresh_token&refresh_token={2}",
-                    self.client_id, self.client_secret, self.token.refresh_token
+                    self.client_id, self.client_secret, refresh_token
                 )),
             )
             .await?;
+        let res = Self::error_for_status(res).await?;
 
-        self.token = res.json::<Token>().await?;
-        self.token_storage.save(&self.token).await?;
+        let mut token = res.json::<Token>().await?;
+        token.user = self.token.read().await.user.clone();
+        *self.token.write().await = token.clone();
+        token_storage
+            .save(&token)
+            .await
+            .map_err(Error::TokenStorage)?;
 
         Ok(())
     }
@@ -102,13 +139,10 @@ impl<T: TokenStorage> Client<T> {
         token_storage: T,
         token: Token,
     ) -> Client<T> {
-        Client {
-            client_id: client_id,
-            client_secret: client_secret,
-            token: token,
-            http_client: reqwest::Client::new(),
-            token_storage: token_storage,
-        }
+        ClientBuilder::new(client_id, client_secret)
+            .token_storage(token_storage)
+            .token(token)
+            .build()
     }
 
     pub async fn from_token(
@@ -117,28 +151,32 @@ impl<T: TokenStorage> Client<T> {
         token_storage: T,
         token: Token,
     ) -> Result<Client<T>> {
-        let mut client =
-            Self::from_token_no_validation(client_id, client_secret, token_storage, token);
-        client.token.user = Some(client.get_user().await?);
+        let client = Self::from_token_no_validation(client_id, client_secret, token_storage, token);
+        let user = client.get_user().await?;
+        client.token.write().await.user = Some(user);
         Ok(client)
     }
 
-    async fn get_app_token(&mut self) -> Result<()> {
-        let token = self
-            .http_client
-            .post("https://id.twitch.tv/oauth2/token")
-            .body(format!(
-                "client_id={0}&client_secret={1}&grant_type=client_credentials",
-                self.client_id, self.client_secret
-            ))
-            .send()
-            .await?
-            .json::<Token>()
+    async fn get_app_token(&self, token_storage: &mut T) -> Result<()> {
+        let res = self
+            .http_request::<()>(
+                reqwest::Method::POST,
+                format!("{0}/token", self.oauth_url),
+                None,
+                Some(format!(
+                    "client_id={0}&client_secret={1}&grant_type=client_credentials",
+                    self.client_id, self.client_secret
+                )),
+            )
             .await?;
+        let mut token = Self::error_for_status(res).await?.json::<Token>().await?;
 
-        self.token = token;
-        self.token.token_type = TokenType::AppAccessToken;
-        self.token_storage.save(&self.token).await?;
+        token.token_type = TokenType::AppAccessToken;
+        *self.token.write().await = token.clone();
+        token_storage
+            .save(&token)
+            .await
+            .map_err(Error::TokenStorage)?;
 
         Ok(())
     }
@@ -148,15 +186,16 @@ impl<T: TokenStorage> Client<T> {
         client_secret: String,
         token_storage: T,
     ) -> Result<Client<T>> {
-        let http_client = reqwest::Client::new();
-        let mut client = Client {
-            client_id: client_id,
-            client_secret: client_secret,
-            http_client: http_client,
-            token_storage: token_storage,
-            token: Token::default(),
-        };
-        client.get_app_token().await?;
+        let client = Self::from_token_no_validation(
+            client_id,
+            client_secret,
+            token_storage,
+            Token {
+                token_type: TokenType::AppAccessToken,
+                ..Default::default()
+            },
+        );
+        client.refresh_token().await?;
         Ok(client)
     }
 
@@ -167,22 +206,31 @@ impl<T: TokenStorage> Client<T> {
         code: String,
         redirect_uri: String,
     ) -> Result<Client<T>> {
-        let http_client = reqwest::Client::new();
-        let token = http_client.post("https://id.twitch.tv/oauth2/token")
-                .body(format!("client_id={client_id}&client_secret={client_secret}&code={code}&grant_type=authorization_code&redirect_uri={redirect_uri}"))
-                .send()
-                .await?
-                .json::<Token>()
-                .await?;
-        let mut client = Client {
-            client_id: client_id,
-            client_secret: client_secret,
-            token: token,
-            http_client: http_client,
-            token_storage: token_storage,
-        };
-        client.token.user = Some(client.get_user().await?);
-        client.token_storage.save(&client.token).await?;
+        let client = Self::from_token_no_validation(
+            client_id,
+            client_secret,
+            token_storage,
+            Token::default(),
+        );
+        let res = client
+            .http_request::<()>(
+                reqwest::Method::POST,
+                format!("{0}/token", client.oauth_url),
+                None,
+                Some(format!("client_id={0}&client_secret={1}&code={code}&grant_type=authorization_code&redirect_uri={redirect_uri}", client.client_id, client.client_secret)),
+            )
+            .await?;
+        *client.token.write().await = Self::error_for_status(res).await?.json::<Token>().await?;
+        let user = client.get_user().await?;
+        client.token.write().await.user = Some(user);
+        let token = client.token.read().await.clone();
+        client
+            .token_storage
+            .lock()
+            .await
+            .save(&token)
+            .await
+            .map_err(Error::TokenStorage)?;
         Ok(client)
     }
 }
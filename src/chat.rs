@@ -7,13 +7,10 @@ use irc::client::Client as IrcClient;
 use irc::client::ClientStream as IrcStream;
 
 impl<T: crate::auth::TokenStorage> crate::helix::Client<T> {
-    pub async fn connect_chat(&mut self, channels: Vec<String>) -> Result<(IrcClient, IrcStream)> {
-        match self.validate_token().await {
-            Err(e) => {
-                println!("{e:?}");
-                bail!("Invalid refresh token or no internet");
-            }
-            _ => {}
+    pub async fn connect_chat(&self, channels: Vec<String>) -> Result<(IrcClient, IrcStream)> {
+        if let Err(e) = self.validate_token().await {
+            println!("{e:?}");
+            bail!("Invalid refresh token or no internet");
         };
 
         let channels = channels
@@ -32,8 +29,8 @@ impl<T: crate::auth::TokenStorage> crate::helix::Client<T> {
             port: Some(6697),
             use_tls: Some(true),
             nickname: Some(self.get_token_user_login().await?.to_lowercase().to_owned()),
-            password: Some(format!("oauth:{0}", self.token.access_token)),
-            channels: channels,
+            password: Some(format!("oauth:{0}", self.token.read().await.access_token)),
+            channels,
             ..Default::default()
         };
 
@@ -44,22 +41,16 @@ impl<T: crate::auth::TokenStorage> crate::helix::Client<T> {
                 bail!("IrcClient::from_config failed");
             }
         };
-        match client.send_cap_req(&[
+        if let Err(e) = client.send_cap_req(&[
             IrcCap::Custom("twitch.tv/tags"),
             IrcCap::Custom("twitch.tv/commands"),
         ]) {
-            Err(e) => {
-                println!("{e:?}");
-                bail!("IrcClient.send_cap_req failed");
-            }
-            _ => {}
+            println!("{e:?}");
+            bail!("IrcClient.send_cap_req failed");
         };
-        match client.identify() {
-            Err(e) => {
-                println!("{e:?}");
-                bail!("IrcClient.identify failed");
-            }
-            _ => {}
+        if let Err(e) = client.identify() {
+            println!("{e:?}");
+            bail!("IrcClient.identify failed");
         };
 
         let stream = match client.stream() {
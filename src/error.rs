@@ -0,0 +1,72 @@
+use chrono::DateTime;
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwitchError {
+    #[serde(default)]
+    pub error: String,
+    pub status: u16,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("HTTP status {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("Twitch error {} ({}): {}", .0.status, .0.error, .0.message)]
+    Twitch(TwitchError),
+    #[error("Rate limited until {reset}")]
+    RateLimited { reset: DateTime<Utc> },
+    #[error("Token refresh failed: {0}")]
+    TokenRefresh(Box<Error>),
+    #[error("Token storage failed: {0}")]
+    TokenStorage(anyhow::Error),
+    #[error("Failed to deserialize response: {source}")]
+    Deserialize {
+        source: serde_json::Error,
+        body: String,
+    },
+    #[error("No user for token")]
+    MissingUser,
+    #[error("No {0} found")]
+    NotFound(&'static str),
+    #[error("Twitch returned no {0}")]
+    EmptyResponse(&'static str),
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("Unexpected EventSub message: {0}")]
+    UnexpectedMessage(String),
+    #[error("EventSub connection closed")]
+    ConnectionClosed,
+    #[error("No EventSub message received within {0:?}")]
+    KeepaliveTimeout(std::time::Duration),
+    #[error("Missing scopes for {subscription_type}: {}", .scopes.join(", "))]
+    MissingScopes {
+        subscription_type: String,
+        scopes: Vec<String>,
+    },
+    #[error("Unknown subscription type {0}")]
+    UnknownSubscriptionType(String),
+    #[error("Invalid condition for {subscription_type}: {message}")]
+    InvalidCondition {
+        subscription_type: String,
+        message: String,
+    },
+    #[error("Invalid webhook signature")]
+    InvalidSignature,
+    #[error("Failed to update {} conduit shards", .0.len())]
+    ConduitShards(Vec<crate::helix::ConduitShardError>),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
@@ -1,17 +1,23 @@
-use anyhow::bail;
-use anyhow::Result;
+use crate::error::{Error, Result};
+use crate::helix::{ConduitShardUpdate, EventSubCondition, EventSubCreate, EventSubTransport};
+use futures::future::BoxFuture;
 use futures::Future;
 use futures::Sink;
 use futures::StreamExt;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use futures::Stream;
 
+use std::collections::{HashSet, VecDeque};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::Context;
 use std::task::Poll;
 
+type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct MessageMetadata {
     pub message_id: String,
@@ -21,6 +27,456 @@ pub struct MessageMetadata {
     pub subscription_version: Option<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    pub capacity: usize,
+    pub window: chrono::Duration,
+    pub max_age: chrono::Duration,
+    seen: VecDeque<(chrono::DateTime<chrono::Utc>, String)>,
+    ids: HashSet<String>,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Deduplicator {
+            capacity: 1000,
+            window: chrono::Duration::minutes(10),
+            max_age: chrono::Duration::minutes(10),
+            seen: VecDeque::new(),
+            ids: HashSet::new(),
+        }
+    }
+}
+
+impl Deduplicator {
+    //Returns false for messages older than max_age or without a readable timestamp.
+    pub fn is_recent(&self, message_id: &str, message_timestamp: &str) -> bool {
+        match chrono::DateTime::parse_from_rfc3339(message_timestamp) {
+            Ok(v) if chrono::Utc::now() - v.with_timezone(&chrono::Utc) > self.max_age => {
+                log::warn!("Dropping message {message_id} from {message_timestamp}");
+                false
+            }
+            Ok(..) => true,
+            Err(e) => {
+                log::warn!(
+                    "Dropping message {message_id} with invalid timestamp {message_timestamp}: {e}"
+                );
+                false
+            }
+        }
+    }
+
+    //Returns false for messages that were already seen or can't be shown to be recent.
+    pub fn check(&mut self, message_id: &str, message_timestamp: &str) -> bool {
+        if !self.is_recent(message_id, message_timestamp) {
+            return false;
+        }
+
+        let now = chrono::Utc::now();
+
+        while let Some((seen_at, id)) = self.seen.front() {
+            if self.seen.len() < self.capacity && now - *seen_at <= self.window {
+                break;
+            }
+            self.ids.remove(id);
+            self.seen.pop_front();
+        }
+
+        if !self.ids.insert(message_id.to_string()) {
+            log::debug!("Dropping duplicate message {message_id}");
+            return false;
+        }
+        self.seen.push_back((now, message_id.to_string()));
+        true
+    }
+}
+
+macro_rules! subscription_types {
+    ($($variant:ident => $name:literal, $version:literal, [$([$($scope:literal)|+]),*], [$($field:ident),*], [$($optional:ident),*];)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum SubscriptionType {
+            $($variant,)*
+        }
+
+        impl SubscriptionType {
+            pub const ALL: &'static [SubscriptionType] = &[$(SubscriptionType::$variant,)*];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(SubscriptionType::$variant => $name,)*
+                }
+            }
+
+            pub fn version(&self) -> &'static str {
+                match self {
+                    $(SubscriptionType::$variant => $version,)*
+                }
+            }
+
+            //Every entry is required, any scope within an entry satisfies it.
+            pub fn scopes(&self) -> &'static [&'static [&'static str]] {
+                match self {
+                    $(SubscriptionType::$variant => &[$(&[$($scope),+]),*],)*
+                }
+            }
+
+            //Required condition fields.
+            pub fn condition_fields(&self) -> &'static [&'static str] {
+                match self {
+                    $(SubscriptionType::$variant => &[$(stringify!($field)),*],)*
+                }
+            }
+
+            //Types without required fields, like channel.raid, take exactly one of these.
+            pub fn optional_condition_fields(&self) -> &'static [&'static str] {
+                match self {
+                    $(SubscriptionType::$variant => &[$(stringify!($optional)),*],)*
+                }
+            }
+        }
+
+        impl std::str::FromStr for SubscriptionType {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                match s {
+                    $($name => Ok(SubscriptionType::$variant),)*
+                    _ => Err(Error::UnknownSubscriptionType(s.to_string())),
+                }
+            }
+        }
+    };
+}
+
+subscription_types! {
+    AutomodMessageHold => "automod.message.hold", "2", [["moderator:manage:automod"]], [broadcaster_user_id, moderator_user_id], [];
+    AutomodMessageUpdate => "automod.message.update", "2", [["moderator:manage:automod"]], [broadcaster_user_id, moderator_user_id], [];
+    AutomodSettingsUpdate => "automod.settings.update", "1", [["moderator:read:automod_settings" | "moderator:manage:automod_settings"]], [broadcaster_user_id, moderator_user_id], [];
+    AutomodTermsUpdate => "automod.terms.update", "1", [["moderator:manage:automod"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelBitsUse => "channel.bits.use", "1", [["bits:read"]], [broadcaster_user_id], [];
+    ChannelUpdate => "channel.update", "2", [], [broadcaster_user_id], [];
+    ChannelFollow => "channel.follow", "2", [["moderator:read:followers"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelAdBreakBegin => "channel.ad_break.begin", "1", [["channel:read:ads"]], [broadcaster_user_id], [];
+    ChannelChatClear => "channel.chat.clear", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatClearUserMessages => "channel.chat.clear_user_messages", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatMessage => "channel.chat.message", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatMessageDelete => "channel.chat.message_delete", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatNotification => "channel.chat.notification", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatSettingsUpdate => "channel.chat_settings.update", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatUserMessageHold => "channel.chat.user_message_hold", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelChatUserMessageUpdate => "channel.chat.user_message_update", "1", [["user:read:chat"]], [broadcaster_user_id, user_id], [];
+    ChannelSharedChatBegin => "channel.shared_chat.begin", "1", [], [broadcaster_user_id], [];
+    ChannelSharedChatUpdate => "channel.shared_chat.update", "1", [], [broadcaster_user_id], [];
+    ChannelSharedChatEnd => "channel.shared_chat.end", "1", [], [broadcaster_user_id], [];
+    ChannelSubscribe => "channel.subscribe", "1", [["channel:read:subscriptions"]], [broadcaster_user_id], [];
+    ChannelSubscriptionEnd => "channel.subscription.end", "1", [["channel:read:subscriptions"]], [broadcaster_user_id], [];
+    ChannelSubscriptionGift => "channel.subscription.gift", "1", [["channel:read:subscriptions"]], [broadcaster_user_id], [];
+    ChannelSubscriptionMessage => "channel.subscription.message", "1", [["channel:read:subscriptions"]], [broadcaster_user_id], [];
+    ChannelCheer => "channel.cheer", "1", [["bits:read"]], [broadcaster_user_id], [];
+    ChannelRaid => "channel.raid", "1", [], [], [from_broadcaster_user_id, to_broadcaster_user_id];
+    ChannelBan => "channel.ban", "1", [["channel:moderate"]], [broadcaster_user_id], [];
+    ChannelUnban => "channel.unban", "1", [["channel:moderate"]], [broadcaster_user_id], [];
+    ChannelUnbanRequestCreate => "channel.unban_request.create", "1", [["moderator:read:unban_requests" | "moderator:manage:unban_requests"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelUnbanRequestResolve => "channel.unban_request.resolve", "1", [["moderator:read:unban_requests" | "moderator:manage:unban_requests"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelModerate => "channel.moderate", "2", [
+        ["moderator:read:blocked_terms" | "moderator:manage:blocked_terms"],
+        ["moderator:read:chat_settings" | "moderator:manage:chat_settings"],
+        ["moderator:read:unban_requests" | "moderator:manage:unban_requests"],
+        ["moderator:read:banned_users" | "moderator:manage:banned_users"],
+        ["moderator:read:chat_messages" | "moderator:manage:chat_messages"],
+        ["moderator:read:warnings" | "moderator:manage:warnings"],
+        ["moderator:read:moderators"],
+        ["moderator:read:vips"]
+    ], [broadcaster_user_id, moderator_user_id], [];
+    ChannelModeratorAdd => "channel.moderator.add", "1", [["moderation:read"]], [broadcaster_user_id], [];
+    ChannelModeratorRemove => "channel.moderator.remove", "1", [["moderation:read"]], [broadcaster_user_id], [];
+    ChannelPointsAutomaticRewardRedemptionAdd => "channel.channel_points_automatic_reward_redemption.add", "2", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [];
+    ChannelPointsCustomRewardAdd => "channel.channel_points_custom_reward.add", "1", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [];
+    ChannelPointsCustomRewardUpdate => "channel.channel_points_custom_reward.update", "1", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [reward_id];
+    ChannelPointsCustomRewardRemove => "channel.channel_points_custom_reward.remove", "1", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [reward_id];
+    ChannelPointsCustomRewardRedemptionAdd => "channel.channel_points_custom_reward_redemption.add", "1", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [reward_id];
+    ChannelPointsCustomRewardRedemptionUpdate => "channel.channel_points_custom_reward_redemption.update", "1", [["channel:read:redemptions" | "channel:manage:redemptions"]], [broadcaster_user_id], [reward_id];
+    ChannelPollBegin => "channel.poll.begin", "1", [["channel:read:polls" | "channel:manage:polls"]], [broadcaster_user_id], [];
+    ChannelPollProgress => "channel.poll.progress", "1", [["channel:read:polls" | "channel:manage:polls"]], [broadcaster_user_id], [];
+    ChannelPollEnd => "channel.poll.end", "1", [["channel:read:polls" | "channel:manage:polls"]], [broadcaster_user_id], [];
+    ChannelPredictionBegin => "channel.prediction.begin", "1", [["channel:read:predictions" | "channel:manage:predictions"]], [broadcaster_user_id], [];
+    ChannelPredictionProgress => "channel.prediction.progress", "1", [["channel:read:predictions" | "channel:manage:predictions"]], [broadcaster_user_id], [];
+    ChannelPredictionLock => "channel.prediction.lock", "1", [["channel:read:predictions" | "channel:manage:predictions"]], [broadcaster_user_id], [];
+    ChannelPredictionEnd => "channel.prediction.end", "1", [["channel:read:predictions" | "channel:manage:predictions"]], [broadcaster_user_id], [];
+    ChannelSuspiciousUserMessage => "channel.suspicious_user.message", "1", [["moderator:read:suspicious_users"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelSuspiciousUserUpdate => "channel.suspicious_user.update", "1", [["moderator:read:suspicious_users"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelVipAdd => "channel.vip.add", "1", [["channel:read:vips" | "channel:manage:vips"]], [broadcaster_user_id], [];
+    ChannelVipRemove => "channel.vip.remove", "1", [["channel:read:vips" | "channel:manage:vips"]], [broadcaster_user_id], [];
+    ChannelWarningAcknowledge => "channel.warning.acknowledge", "1", [["moderator:read:warnings" | "moderator:manage:warnings"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelWarningSend => "channel.warning.send", "1", [["moderator:read:warnings" | "moderator:manage:warnings"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelCharityCampaignDonate => "channel.charity_campaign.donate", "1", [["channel:read:charity"]], [broadcaster_user_id], [];
+    ChannelCharityCampaignStart => "channel.charity_campaign.start", "1", [["channel:read:charity"]], [broadcaster_user_id], [];
+    ChannelCharityCampaignProgress => "channel.charity_campaign.progress", "1", [["channel:read:charity"]], [broadcaster_user_id], [];
+    ChannelCharityCampaignStop => "channel.charity_campaign.stop", "1", [["channel:read:charity"]], [broadcaster_user_id], [];
+    ConduitShardDisabled => "conduit.shard.disabled", "1", [], [client_id], [conduit_id];
+    DropEntitlementGrant => "drop.entitlement.grant", "1", [], [organization_id], [category_id, campaign_id];
+    ExtensionBitsTransactionCreate => "extension.bits_transaction.create", "1", [], [extension_client_id], [];
+    ChannelGoalBegin => "channel.goal.begin", "1", [["channel:read:goals"]], [broadcaster_user_id], [];
+    ChannelGoalProgress => "channel.goal.progress", "1", [["channel:read:goals"]], [broadcaster_user_id], [];
+    ChannelGoalEnd => "channel.goal.end", "1", [["channel:read:goals"]], [broadcaster_user_id], [];
+    ChannelHypeTrainBegin => "channel.hype_train.begin", "2", [["channel:read:hype_train"]], [broadcaster_user_id], [];
+    ChannelHypeTrainProgress => "channel.hype_train.progress", "2", [["channel:read:hype_train"]], [broadcaster_user_id], [];
+    ChannelHypeTrainEnd => "channel.hype_train.end", "2", [["channel:read:hype_train"]], [broadcaster_user_id], [];
+    ChannelShieldModeBegin => "channel.shield_mode.begin", "1", [["moderator:read:shield_mode" | "moderator:manage:shield_mode"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelShieldModeEnd => "channel.shield_mode.end", "1", [["moderator:read:shield_mode" | "moderator:manage:shield_mode"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelShoutoutCreate => "channel.shoutout.create", "1", [["moderator:read:shoutouts" | "moderator:manage:shoutouts"]], [broadcaster_user_id, moderator_user_id], [];
+    ChannelShoutoutReceive => "channel.shoutout.receive", "1", [["moderator:read:shoutouts" | "moderator:manage:shoutouts"]], [broadcaster_user_id, moderator_user_id], [];
+    StreamOnline => "stream.online", "1", [], [broadcaster_user_id], [];
+    StreamOffline => "stream.offline", "1", [], [broadcaster_user_id], [];
+    UserAuthorizationGrant => "user.authorization.grant", "1", [], [client_id], [];
+    UserAuthorizationRevoke => "user.authorization.revoke", "1", [], [client_id], [];
+    UserUpdate => "user.update", "1", [], [user_id], [];
+    UserWhisperMessage => "user.whisper.message", "1", [["user:read:whispers" | "user:manage:whispers"]], [user_id], [];
+}
+
+impl std::fmt::Display for SubscriptionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl SubscriptionType {
+    pub fn check_condition(&self, condition: &EventSubCondition) -> Result<()> {
+        let set: Vec<String> = match serde_json::to_value(condition) {
+            Ok(serde_json::Value::Object(fields)) => fields
+                .into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, _)| k)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let invalid = |message: String| Error::InvalidCondition {
+            subscription_type: self.to_string(),
+            message,
+        };
+
+        let required = self.condition_fields();
+        let optional = self.optional_condition_fields();
+        if let Some(field) = set
+            .iter()
+            .find(|f| !required.contains(&f.as_str()) && !optional.contains(&f.as_str()))
+        {
+            return Err(invalid(format!("unexpected {field}")));
+        }
+
+        let missing: Vec<&str> = required
+            .iter()
+            .filter(|f| !set.iter().any(|s| s == *f))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            return Err(invalid(format!("missing {0}", missing.join(", "))));
+        }
+
+        if required.is_empty() && set.len() != 1 {
+            return Err(invalid(format!(
+                "expected exactly one of {0}",
+                optional.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn missing_scopes(&self, scopes: &[String]) -> Vec<String> {
+        self.scopes()
+            .iter()
+            .filter(|any| !any.iter().any(|scope| scopes.iter().any(|s| s == scope)))
+            .map(|any| any.join(" or "))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub r#type: String,
+    pub version: String,
+    pub condition: EventSubCondition,
+}
+
+impl Subscription {
+    pub fn new(r#type: SubscriptionType, condition: EventSubCondition) -> Subscription {
+        Subscription {
+            r#type: r#type.as_str().to_string(),
+            version: r#type.version().to_string(),
+            condition,
+        }
+    }
+
+    //Fills the type's required condition fields in catalogue order, e.g. broadcaster_user_id and
+    //user_id for channel.chat.message. Use new() to set optional fields.
+    pub fn from_ids(r#type: SubscriptionType, ids: Vec<String>) -> Result<Subscription> {
+        let fields = r#type.condition_fields();
+        if ids.len() != fields.len() {
+            return Err(Error::InvalidCondition {
+                subscription_type: r#type.to_string(),
+                message: format!("expected ids for {0}", fields.join(", ")),
+            });
+        }
+
+        let condition: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .map(|f| f.to_string())
+            .zip(ids.into_iter().map(serde_json::Value::String))
+            .collect();
+        let condition: EventSubCondition =
+            serde_json::from_value(serde_json::Value::Object(condition)).map_err(|source| {
+                Error::Deserialize {
+                    source,
+                    body: r#type.to_string(),
+                }
+            })?;
+        r#type.check_condition(&condition)?;
+        Ok(Subscription::new(r#type, condition))
+    }
+
+    pub fn subscription_type(&self) -> Option<SubscriptionType> {
+        self.r#type.parse().ok()
+    }
+
+    fn broadcaster(r#type: SubscriptionType, broadcaster_id: String) -> Subscription {
+        Subscription::new(
+            r#type,
+            EventSubCondition {
+                broadcaster_user_id: Some(broadcaster_id),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn channel_update(broadcaster_id: String) -> Subscription {
+        Subscription::broadcaster(SubscriptionType::ChannelUpdate, broadcaster_id)
+    }
+
+    pub fn channel_follow(broadcaster_id: String, moderator_id: String) -> Subscription {
+        Subscription::new(
+            SubscriptionType::ChannelFollow,
+            EventSubCondition {
+                broadcaster_user_id: Some(broadcaster_id),
+                moderator_user_id: Some(moderator_id),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn channel_raid_to(broadcaster_id: String) -> Subscription {
+        Subscription::new(
+            SubscriptionType::ChannelRaid,
+            EventSubCondition {
+                to_broadcaster_user_id: Some(broadcaster_id),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn channel_raid_from(broadcaster_id: String) -> Subscription {
+        Subscription::new(
+            SubscriptionType::ChannelRaid,
+            EventSubCondition {
+                from_broadcaster_user_id: Some(broadcaster_id),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn custom_reward_redemption_add(
+        broadcaster_id: String,
+        reward_id: Option<String>,
+    ) -> Subscription {
+        let mut subscription = Subscription::broadcaster(
+            SubscriptionType::ChannelPointsCustomRewardRedemptionAdd,
+            broadcaster_id,
+        );
+        subscription.condition.reward_id = reward_id;
+        subscription
+    }
+
+    pub fn custom_reward_redemption_update(
+        broadcaster_id: String,
+        reward_id: Option<String>,
+    ) -> Subscription {
+        let mut subscription = Subscription::broadcaster(
+            SubscriptionType::ChannelPointsCustomRewardRedemptionUpdate,
+            broadcaster_id,
+        );
+        subscription.condition.reward_id = reward_id;
+        subscription
+    }
+
+    pub fn custom_reward_add(broadcaster_id: String) -> Subscription {
+        Subscription::broadcaster(
+            SubscriptionType::ChannelPointsCustomRewardAdd,
+            broadcaster_id,
+        )
+    }
+
+    pub fn custom_reward_update(broadcaster_id: String, reward_id: Option<String>) -> Subscription {
+        let mut subscription = Subscription::broadcaster(
+            SubscriptionType::ChannelPointsCustomRewardUpdate,
+            broadcaster_id,
+        );
+        subscription.condition.reward_id = reward_id;
+        subscription
+    }
+
+    pub fn custom_reward_remove(broadcaster_id: String, reward_id: Option<String>) -> Subscription {
+        let mut subscription = Subscription::broadcaster(
+            SubscriptionType::ChannelPointsCustomRewardRemove,
+            broadcaster_id,
+        );
+        subscription.condition.reward_id = reward_id;
+        subscription
+    }
+
+    pub fn stream_online(broadcaster_id: String) -> Subscription {
+        Subscription::broadcaster(SubscriptionType::StreamOnline, broadcaster_id)
+    }
+
+    pub fn stream_offline(broadcaster_id: String) -> Subscription {
+        Subscription::broadcaster(SubscriptionType::StreamOffline, broadcaster_id)
+    }
+
+    //Everything needed to follow a channel's chat as user_id.
+    pub fn chat(broadcaster_id: String, user_id: String) -> Vec<Subscription> {
+        [
+            SubscriptionType::ChannelChatMessage,
+            SubscriptionType::ChannelChatNotification,
+            SubscriptionType::ChannelChatClear,
+            SubscriptionType::ChannelChatClearUserMessages,
+            SubscriptionType::ChannelChatMessageDelete,
+            SubscriptionType::ChannelChatSettingsUpdate,
+        ]
+        .into_iter()
+        .map(|subscription_type| {
+            Subscription::new(
+                subscription_type,
+                EventSubCondition {
+                    broadcaster_user_id: Some(broadcaster_id.clone()),
+                    user_id: Some(user_id.clone()),
+                    ..Default::default()
+                },
+            )
+        })
+        .collect()
+    }
+
+    fn into_create(self) -> EventSubCreate {
+        EventSubCreate {
+            r#type: self.r#type,
+            version: self.version,
+            condition: self.condition,
+            transport: EventSubTransport {
+                method: "websocket".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Message {
     pub metadata: MessageMetadata,
@@ -33,7 +489,7 @@ pub struct SessionWelcomeSession {
     pub connected_at: String,
     pub status: String,
     pub reconnect_url: Option<String>,
-    pub keepalive_timeout_seconds: i64,
+    pub keepalive_timeout_seconds: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -73,14 +529,62 @@ pub struct ChannelUpdate {
 #[derive(Debug, Deserialize, Clone)]
 pub struct CustomRewardRedemptionAddReward {
     pub id: String,
+    pub title: String,
+    pub cost: i64,
+    pub prompt: String,
 }
 
+//Used for both channel.channel_points_custom_reward_redemption.add and .update.
 #[derive(Debug, Deserialize, Clone)]
 pub struct CustomRewardRedemptionAdd {
     pub id: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub user_id: String,
     pub user_login: String,
+    pub user_name: String,
     pub user_input: String,
+    pub status: String,
     pub reward: CustomRewardRedemptionAddReward,
+    pub redeemed_at: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CustomRewardLimit {
+    pub is_enabled: bool,
+    pub value: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CustomRewardGlobalCooldown {
+    pub is_enabled: bool,
+    pub seconds: i64,
+}
+
+//Used for channel.channel_points_custom_reward.add, update and remove.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CustomReward {
+    pub id: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub is_enabled: bool,
+    pub is_paused: bool,
+    pub is_in_stock: bool,
+    pub title: String,
+    pub cost: i64,
+    pub prompt: String,
+    pub is_user_input_required: bool,
+    pub should_redemptions_skip_request_queue: bool,
+    pub max_per_stream: CustomRewardLimit,
+    pub max_per_user_per_stream: CustomRewardLimit,
+    pub background_color: String,
+    pub image: Option<crate::helix::RewardImage>,
+    pub default_image: crate::helix::RewardImage,
+    pub global_cooldown: CustomRewardGlobalCooldown,
+    pub cooldown_expires_at: Option<String>,
+    pub redemptions_redeemed_current_stream: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -101,281 +605,1509 @@ pub struct StreamOffline {
 }
 
 #[derive(Debug, Deserialize, Clone)]
-#[cfg_attr(feature = "bevy", derive(bevy_ecs::event::Event))]
-pub enum NotificationType {
-    ChannelUpdate(ChannelUpdate),
-    CustomRewardRedemptionAdd(CustomRewardRedemptionAdd),
-    StreamOnline(StreamOnline),
-    StreamOffline(StreamOffline),
-    Other(serde_json::Value),
-    ChannelFollow(ChannelFollow),
+pub struct ChannelSubscribe {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub tier: String,
+    pub is_gift: bool,
 }
 
-#[derive(Debug)]
-#[cfg_attr(
-    feature = "bevy",
-    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
-)]
-pub struct Client {
-    inner_stream: Pin<
-        Box<
-            tokio_tungstenite::WebSocketStream<
-                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
-            >,
-        >,
-    >,
-    ping_sleep: Pin<Box<tokio::time::Sleep>>,
-    pub session_id: String,
-    pub last_error: Option<anyhow::Error>,
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelSubscriptionGift {
+    pub user_id: Option<String>,
+    pub user_login: Option<String>,
+    pub user_name: Option<String>,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub total: i64,
+    pub tier: String,
+    pub cumulative_total: Option<i64>,
+    pub is_anonymous: bool,
 }
 
-impl Stream for Client {
-    type Item = NotificationType;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.get_mut();
-        let mut inner_stream = this.inner_stream.as_mut();
-
-        match this.ping_sleep.as_mut().poll(cx) {
-            Poll::Pending => {}
-            Poll::Ready(..) => {
-                this.ping_sleep
-                    .as_mut()
-                    .reset(tokio::time::Instant::now() + tokio::time::Duration::from_secs(30));
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubscriptionMessageEmote {
+    pub begin: i64,
+    pub end: i64,
+    pub id: String,
+}
 
-                match inner_stream.as_mut().start_send(
-                    tokio_tungstenite::tungstenite::protocol::Message::Ping(vec![]),
-                ) {
-                    Err(e) => {
-                        log::warn!("Failed to send ping: {e}");
-                        return Poll::Ready(None);
-                    }
-                    _ => {}
-                };
-            }
-        };
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubscriptionMessageText {
+    pub text: String,
+    #[serde(default)]
+    pub emotes: Option<Vec<SubscriptionMessageEmote>>,
+}
 
-        loop {
-            match inner_stream.as_mut().poll_next(cx) {
-                Poll::Pending => return Poll::Pending,
-                Poll::Ready(v) => {
-                    match v {
-                        Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Ping(..))) => {
-                            match inner_stream.as_mut().start_send(
-                                tokio_tungstenite::tungstenite::protocol::Message::Pong(vec![]),
-                            ) {
-                                Ok(()) => continue,
-                                Err(e) => {
-                                    log::error!("Failed to send pong: {e}");
-                                    this.last_error.replace(e.into());
-                                    break;
-                                }
-                            };
-                        }
-                        Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Text(text))) => {
-                            let message: Message = match serde_json::from_str(&text) {
-                                Ok(v) => v,
-                                Err(e) => {
-                                    log::error!("Failed to parse message: {e}");
-                                    break;
-                                }
-                            };
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelSubscriptionMessage {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub tier: String,
+    pub message: SubscriptionMessageText,
+    pub cumulative_months: i64,
+    pub streak_months: Option<i64>,
+    pub duration_months: i64,
+}
 
-                            match message.metadata.message_type.as_str() {
-                                "notification" => {
-                                    let subtype = match &message.metadata.subscription_type {
-                                        Some(v) => v,
-                                        None => break,
-                                    };
-
-                                    let notification: Notification =
-                                        match serde_json::from_value(message.payload.clone()) {
-                                            Ok(v) => v,
-                                            Err(e) => {
-                                                log::error!(
-                                                    "Failed to parse notification payload: {e}"
-                                                );
-                                                break;
-                                            }
-                                        };
-
-                                    match subtype.as_str() {
-                                        "channel.update" => {
-                                            let event: ChannelUpdate =
-                                                match serde_json::from_value(notification.event) {
-                                                    Ok(v) => v,
-                                                    Err(e) => {
-                                                        log::error!(
-                                                    "Failed to parse channel.update payload: {e}"
-                                                );
-                                                        break;
-                                                    }
-                                                };
-
-                                            return Poll::Ready(Some(
-                                                NotificationType::ChannelUpdate(event),
-                                            ));
-                                        }
-                                        "channel.channel_points_custom_reward_redemption.add" => {
-                                            let event: CustomRewardRedemptionAdd =
-                                                match serde_json::from_value(notification.event) {
-                                                    Ok(v) => v,
-                                                    Err(e) => {
-                                                        log::error!(
-                                                        "Failed to parse channel.channel_points_custom_reward_redemption.add payload: {e}"
-                                                    );
-                                                        break;
-                                                    }
-                                                };
-
-                                            return Poll::Ready(Some(
-                                                NotificationType::CustomRewardRedemptionAdd(event),
-                                            ));
-                                        }
-                                        "channel.follow" => {
-                                            let event: ChannelFollow = match serde_json::from_value(
-                                                notification.event,
-                                            ) {
-                                                Ok(v) => v,
-                                                Err(e) => {
-                                                    log::error!("Failed to parse channel.follow payload: {e}");
-                                                    break;
-                                                }
-                                            };
-                                            return Poll::Ready(Some(
-                                                NotificationType::ChannelFollow(event),
-                                            ));
-                                        }
-                                        "stream.online" => {
-                                            let event: StreamOnline = match serde_json::from_value(
-                                                notification.event,
-                                            ) {
-                                                Ok(v) => v,
-                                                Err(e) => {
-                                                    log::error!("Failed to parse stream.online payload: {e}");
-                                                    break;
-                                                }
-                                            };
-
-                                            return Poll::Ready(Some(
-                                                NotificationType::StreamOnline(event),
-                                            ));
-                                        }
-                                        "stream.offline" => {
-                                            let event: StreamOffline = match serde_json::from_value(
-                                                notification.event,
-                                            ) {
-                                                Ok(v) => v,
-                                                Err(e) => {
-                                                    log::error!("Failed to parse stream.offline payload: {e}");
-                                                    break;
-                                                }
-                                            };
-
-                                            return Poll::Ready(Some(
-                                                NotificationType::StreamOffline(event),
-                                            ));
-                                        }
-                                        _ => {
-                                            return Poll::Ready(Some(NotificationType::Other(
-                                                message.payload,
-                                            )))
-                                        }
-                                    }
-                                }
-                                _ => continue,
-                            }
-                        }
-                        Some(Ok(r)) => {
-                            log::trace!("Unknown message: {r:?}");
-                            continue;
-                        }
-                        Some(Err(e)) => {
-                            log::error!("Failed to receive message: {e}");
-                            this.last_error.replace(e.into());
-                            break;
-                        }
-                        None => break,
-                    }
-                }
-            }
-        }
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelCheer {
+    pub is_anonymous: bool,
+    pub user_id: Option<String>,
+    pub user_login: Option<String>,
+    pub user_name: Option<String>,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub message: String,
+    pub bits: i64,
+}
 
-        Poll::Ready(None)
-    }
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelRaid {
+    pub from_broadcaster_user_id: String,
+    pub from_broadcaster_user_login: String,
+    pub from_broadcaster_user_name: String,
+    pub to_broadcaster_user_id: String,
+    pub to_broadcaster_user_login: String,
+    pub to_broadcaster_user_name: String,
+    pub viewers: i64,
 }
 
-impl<T: crate::auth::TokenStorage> crate::helix::Client<T> {
-    pub async fn connect_eventsub(&mut self, topics: Vec<(String, String)>) -> Result<Client> {
-        let (mut ws_stream, _) =
-            match tokio_tungstenite::connect_async("wss://eventsub.wss.twitch.tv/ws").await {
-                Ok(v) => v,
-                Err(e) => return Err(e.into()),
-            };
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelBan {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub moderator_user_id: String,
+    pub moderator_user_login: String,
+    pub moderator_user_name: String,
+    pub reason: String,
+    pub banned_at: String,
+    pub ends_at: Option<String>,
+    pub is_permanent: bool,
+}
 
-        let welcome = loop {
-            let msg = ws_stream.next().await;
-            match msg {
-                Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Text(text))) => {
-                    let message: Message = match serde_json::from_str(&text) {
-                        Ok(v) => v,
-                        Err(e) => return Err(e.into()),
-                    };
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelUnban {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub moderator_user_id: String,
+    pub moderator_user_login: String,
+    pub moderator_user_name: String,
+}
 
-                    if message.metadata.message_type.as_str() != "session_welcome" {
-                        bail!("No session welcome");
-                    }
+//Used for both channel.moderator.add and channel.moderator.remove.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelModerator {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+}
 
-                    let welcome: SessionWelcome =
-                        match serde_json::from_value(message.payload.clone()) {
-                            Ok(v) => v,
-                            Err(e) => return Err(e.into()),
-                        };
+#[derive(Debug, Deserialize, Clone)]
+pub struct PollChoice {
+    pub id: String,
+    pub title: String,
+    pub bits_votes: Option<i64>,
+    pub channel_points_votes: Option<i64>,
+    pub votes: Option<i64>,
+}
 
-                    break welcome;
-                }
-                Some(Err(e)) => return Err(e.into()),
-                Some(..) => {}
-                None => bail!("WebSocket dropped"),
-            }
-        };
+#[derive(Debug, Deserialize, Clone)]
+pub struct PollVoting {
+    pub is_enabled: bool,
+    pub amount_per_vote: i64,
+}
 
-        let broadcaster_id = match self.get_token_user_id().await {
-            Ok(v) => v,
-            Err(..) => bail!("No token user id"),
-        };
-        for (subtype, version) in topics.into_iter() {
-            match self
-                .create_eventsub_subscription(&crate::helix::EventSubCreate {
-                    r#type: subtype,
-                    version: version,
-                    condition: crate::helix::EventSubCondition {
-                        broadcaster_id: Some(broadcaster_id.clone()),
-                        broadcaster_user_id: Some(broadcaster_id.clone()),
-                        moderator_user_id: Some(broadcaster_id.clone()),
-                        user_id: Some(broadcaster_id.clone()),
-                        ..Default::default()
-                    },
-                    transport: crate::helix::EventSubTransport {
-                        method: "websocket".to_string(),
-                        session_id: Some(welcome.session.id.clone()),
-                        ..Default::default()
-                    },
-                })
-                .await
-            {
-                Ok(..) => {}
-                Err(e) => {
-                    bail!("create_eventsub_subscription failed: {e:?}")
-                }
+//Used for channel.poll.begin, progress and end. ends_at is set until the poll ends, ended_at and status afterwards.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelPoll {
+    pub id: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub title: String,
+    pub choices: Vec<PollChoice>,
+    pub bits_voting: PollVoting,
+    pub channel_points_voting: PollVoting,
+    pub started_at: String,
+    pub ends_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PredictionPredictor {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub channel_points_won: Option<i64>,
+    pub channel_points_used: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PredictionOutcome {
+    pub id: String,
+    pub title: String,
+    pub color: String,
+    pub users: Option<i64>,
+    pub channel_points: Option<i64>,
+    pub top_predictors: Option<Vec<PredictionPredictor>>,
+}
+
+//Used for channel.prediction.begin, progress, lock and end.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelPrediction {
+    pub id: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub title: String,
+    pub outcomes: Vec<PredictionOutcome>,
+    pub started_at: String,
+    pub locks_at: Option<String>,
+    pub locked_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub winning_outcome_id: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HypeTrainContribution {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub r#type: String,
+    pub total: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HypeTrainParticipant {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+}
+
+//Used for channel.hype_train.begin, progress and end.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelHypeTrain {
+    pub id: String,
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub level: i64,
+    pub total: i64,
+    pub progress: Option<i64>,
+    pub goal: Option<i64>,
+    #[serde(default)]
+    pub top_contributions: Vec<HypeTrainContribution>,
+    pub all_time_high_level: Option<i64>,
+    pub all_time_high_total: Option<i64>,
+    #[serde(default)]
+    pub shared_train_participants: Option<Vec<HypeTrainParticipant>>,
+    pub r#type: String,
+    pub is_shared_train: bool,
+    pub started_at: String,
+    pub expires_at: Option<String>,
+    pub ended_at: Option<String>,
+    pub cooldown_ends_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatCheermote {
+    pub prefix: String,
+    pub bits: i64,
+    pub tier: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatEmote {
+    pub id: String,
+    pub emote_set_id: String,
+    pub owner_id: Option<String>,
+    pub format: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatMention {
+    pub user_id: String,
+    pub user_name: String,
+    pub user_login: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatMessageFragment {
+    Text {
+        text: String,
+    },
+    Cheermote {
+        text: String,
+        cheermote: ChatCheermote,
+    },
+    Emote {
+        text: String,
+        emote: ChatEmote,
+    },
+    Mention {
+        text: String,
+        mention: ChatMention,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatMessageBody {
+    pub text: String,
+    pub fragments: Vec<ChatMessageFragment>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatBadge {
+    pub set_id: String,
+    pub id: String,
+    pub info: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatCheer {
+    pub bits: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatReply {
+    pub parent_message_id: String,
+    pub parent_message_body: String,
+    pub parent_user_id: String,
+    pub parent_user_name: String,
+    pub parent_user_login: String,
+    pub thread_message_id: String,
+    pub thread_user_id: String,
+    pub thread_user_name: String,
+    pub thread_user_login: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatMessage {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub chatter_user_id: String,
+    pub chatter_user_login: String,
+    pub chatter_user_name: String,
+    pub message_id: String,
+    pub message: ChatMessageBody,
+    pub message_type: String,
+    pub badges: Vec<ChatBadge>,
+    pub cheer: Option<ChatCheer>,
+    pub color: String,
+    pub reply: Option<ChatReply>,
+    pub channel_points_custom_reward_id: Option<String>,
+    //Only set for messages sent from another channel in a shared chat session.
+    pub source_broadcaster_user_id: Option<String>,
+    pub source_broadcaster_user_login: Option<String>,
+    pub source_broadcaster_user_name: Option<String>,
+    pub source_message_id: Option<String>,
+    pub source_badges: Option<Vec<ChatBadge>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeSub {
+    pub sub_tier: String,
+    pub is_prime: bool,
+    pub duration_months: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeResub {
+    pub cumulative_months: i64,
+    pub duration_months: i64,
+    pub streak_months: Option<i64>,
+    pub sub_tier: String,
+    pub is_prime: Option<bool>,
+    pub is_gift: bool,
+    pub gifter_is_anonymous: Option<bool>,
+    pub gifter_user_id: Option<String>,
+    pub gifter_user_name: Option<String>,
+    pub gifter_user_login: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeSubGift {
+    pub duration_months: i64,
+    pub cumulative_total: Option<i64>,
+    pub recipient_user_id: String,
+    pub recipient_user_name: String,
+    pub recipient_user_login: String,
+    pub sub_tier: String,
+    pub community_gift_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeCommunitySubGift {
+    pub id: String,
+    pub total: i64,
+    pub sub_tier: String,
+    pub cumulative_total: Option<i64>,
+}
+
+//Used for gift_paid_upgrade and pay_it_forward.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeGifter {
+    pub gifter_is_anonymous: bool,
+    pub gifter_user_id: Option<String>,
+    pub gifter_user_name: Option<String>,
+    pub gifter_user_login: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticePrimePaidUpgrade {
+    pub sub_tier: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeRaid {
+    pub user_id: String,
+    pub user_name: String,
+    pub user_login: String,
+    pub viewer_count: i64,
+    pub profile_image_url: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeAnnouncement {
+    pub color: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeAmount {
+    pub value: i64,
+    pub decimal_places: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeCharityDonation {
+    pub charity_name: String,
+    pub amount: ChatNoticeAmount,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChatNoticeBitsBadgeTier {
+    pub tier: i64,
+}
+
+//notice_type names which of the notice fields is set, shared_chat_* notices come from another
+//channel in a shared chat session.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatNotification {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub chatter_user_id: String,
+    pub chatter_user_login: String,
+    pub chatter_user_name: String,
+    pub chatter_is_anonymous: bool,
+    pub color: String,
+    pub badges: Vec<ChatBadge>,
+    pub system_message: String,
+    pub message_id: String,
+    pub message: ChatMessageBody,
+    pub notice_type: String,
+    pub sub: Option<ChatNoticeSub>,
+    pub resub: Option<ChatNoticeResub>,
+    pub sub_gift: Option<ChatNoticeSubGift>,
+    pub community_sub_gift: Option<ChatNoticeCommunitySubGift>,
+    pub gift_paid_upgrade: Option<ChatNoticeGifter>,
+    pub prime_paid_upgrade: Option<ChatNoticePrimePaidUpgrade>,
+    pub raid: Option<ChatNoticeRaid>,
+    pub pay_it_forward: Option<ChatNoticeGifter>,
+    pub announcement: Option<ChatNoticeAnnouncement>,
+    pub charity_donation: Option<ChatNoticeCharityDonation>,
+    pub bits_badge_tier: Option<ChatNoticeBitsBadgeTier>,
+    pub shared_chat_sub: Option<ChatNoticeSub>,
+    pub shared_chat_resub: Option<ChatNoticeResub>,
+    pub shared_chat_sub_gift: Option<ChatNoticeSubGift>,
+    pub shared_chat_community_sub_gift: Option<ChatNoticeCommunitySubGift>,
+    pub shared_chat_gift_paid_upgrade: Option<ChatNoticeGifter>,
+    pub shared_chat_prime_paid_upgrade: Option<ChatNoticePrimePaidUpgrade>,
+    pub shared_chat_raid: Option<ChatNoticeRaid>,
+    pub shared_chat_pay_it_forward: Option<ChatNoticeGifter>,
+    pub shared_chat_announcement: Option<ChatNoticeAnnouncement>,
+    pub source_broadcaster_user_id: Option<String>,
+    pub source_broadcaster_user_login: Option<String>,
+    pub source_broadcaster_user_name: Option<String>,
+    pub source_message_id: Option<String>,
+    pub source_badges: Option<Vec<ChatBadge>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatClear {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatClearUserMessages {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub target_user_id: String,
+    pub target_user_name: String,
+    pub target_user_login: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatMessageDelete {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub target_user_id: String,
+    pub target_user_name: String,
+    pub target_user_login: String,
+    pub message_id: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelChatSettingsUpdate {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub emote_mode: bool,
+    pub follower_mode: bool,
+    pub follower_mode_duration_minutes: Option<i64>,
+    pub slow_mode: bool,
+    pub slow_mode_wait_time_seconds: Option<i64>,
+    pub subscriber_mode: bool,
+    pub unique_chat_mode: bool,
+}
+
+//A message that could not be parsed, kept so the stream can carry on.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Malformed {
+    pub payload: serde_json::Value,
+    pub error: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Revocation {
+    pub subscription: crate::helix::EventSub,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::event::Event))]
+#[allow(clippy::large_enum_variant)]
+pub enum NotificationType {
+    ChannelUpdate(ChannelUpdate),
+    CustomRewardRedemptionAdd(CustomRewardRedemptionAdd),
+    StreamOnline(StreamOnline),
+    StreamOffline(StreamOffline),
+    Other(serde_json::Value),
+    ChannelFollow(ChannelFollow),
+    Revocation(Revocation),
+    ChannelSubscribe(ChannelSubscribe),
+    ChannelSubscriptionEnd(ChannelSubscribe),
+    ChannelSubscriptionGift(ChannelSubscriptionGift),
+    ChannelSubscriptionMessage(ChannelSubscriptionMessage),
+    ChannelCheer(ChannelCheer),
+    ChannelRaid(ChannelRaid),
+    ChannelBan(ChannelBan),
+    ChannelUnban(ChannelUnban),
+    ChannelModeratorAdd(ChannelModerator),
+    ChannelModeratorRemove(ChannelModerator),
+    ChannelPollBegin(ChannelPoll),
+    ChannelPollProgress(ChannelPoll),
+    ChannelPollEnd(ChannelPoll),
+    ChannelPredictionBegin(ChannelPrediction),
+    ChannelPredictionProgress(ChannelPrediction),
+    ChannelPredictionLock(ChannelPrediction),
+    ChannelPredictionEnd(ChannelPrediction),
+    ChannelHypeTrainBegin(ChannelHypeTrain),
+    ChannelHypeTrainProgress(ChannelHypeTrain),
+    ChannelHypeTrainEnd(ChannelHypeTrain),
+    ChannelChatMessage(ChannelChatMessage),
+    ChannelChatNotification(ChannelChatNotification),
+    ChannelChatClear(ChannelChatClear),
+    ChannelChatClearUserMessages(ChannelChatClearUserMessages),
+    ChannelChatMessageDelete(ChannelChatMessageDelete),
+    ChannelChatSettingsUpdate(ChannelChatSettingsUpdate),
+    CustomRewardRedemptionUpdate(CustomRewardRedemptionAdd),
+    CustomRewardAdd(CustomReward),
+    CustomRewardUpdate(CustomReward),
+    CustomRewardRemove(CustomReward),
+    Malformed(Malformed),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::event::Event))]
+#[allow(clippy::large_enum_variant)]
+pub enum ChatEvent {
+    Message(ChannelChatMessage),
+    Notification(ChannelChatNotification),
+    Clear(ChannelChatClear),
+    ClearUserMessages(ChannelChatClearUserMessages),
+    MessageDelete(ChannelChatMessageDelete),
+    SettingsUpdate(ChannelChatSettingsUpdate),
+    Revocation(Revocation),
+    Malformed(Malformed),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::event::Event))]
+pub struct Envelope {
+    pub metadata: MessageMetadata,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub subscription: crate::helix::EventSub,
+    pub event: NotificationType,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "bevy", derive(bevy_ecs::event::Event))]
+pub struct ChatEnvelope {
+    pub metadata: MessageMetadata,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub subscription: crate::helix::EventSub,
+    pub event: ChatEvent,
+}
+
+#[derive(Debug)]
+pub enum ReconnectEvent {
+    Disconnected(Error),
+    Reconnecting {
+        attempt: u32,
+        delay: tokio::time::Duration,
+    },
+    Failed {
+        attempt: u32,
+        error: Error,
+    },
+    Reconnected {
+        session_id: String,
+    },
+    //Resuming failed with an error that retrying won't fix, the stream ends with it as last_error.
+    GaveUp {
+        attempt: u32,
+    },
+}
+
+//Client errors such as missing scopes or an invalid condition won't go away by retrying.
+fn is_retryable(error: &Error) -> bool {
+    let retryable_status =
+        |status: u16| !(400..500).contains(&status) || status == 408 || status == 429;
+    match error {
+        Error::Twitch(e) => retryable_status(e.status),
+        Error::Status { status, .. } => retryable_status(status.as_u16()),
+        Error::TokenRefresh(e) => is_retryable(e),
+        Error::MissingScopes { .. } | Error::MissingUser => false,
+        _ => true,
+    }
+}
+
+type Resubscribe = Arc<dyn Fn(String) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+#[derive(Clone)]
+struct Resilient {
+    url: String,
+    resubscribe: Resubscribe,
+    events: tokio::sync::mpsc::UnboundedSender<ReconnectEvent>,
+}
+
+impl std::fmt::Debug for Resilient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resilient").field("url", &self.url).finish()
+    }
+}
+
+impl Resilient {
+    async fn resume(self, error: Error) -> Result<(WebSocketStream, SessionWelcome)> {
+        log::warn!("EventSub connection lost: {error}");
+        let _ = self.events.send(ReconnectEvent::Disconnected(error));
+
+        let mut attempt = 0;
+        loop {
+            //Retry right away once, then back off exponentially up to 64 seconds.
+            let delay = match attempt {
+                0 => tokio::time::Duration::ZERO,
+                n => tokio::time::Duration::from_secs(1 << (n - 1).min(6)),
             };
+            attempt += 1;
+
+            let _ = self
+                .events
+                .send(ReconnectEvent::Reconnecting { attempt, delay });
+            tokio::time::sleep(delay).await;
+
+            match self.connect().await {
+                Ok((ws_stream, welcome)) => {
+                    let _ = self.events.send(ReconnectEvent::Reconnected {
+                        session_id: welcome.session.id.clone(),
+                    });
+                    return Ok((ws_stream, welcome));
+                }
+                Err(error) if !is_retryable(&error) => {
+                    log::error!("Giving up on reconnecting: {error}");
+                    let _ = self.events.send(ReconnectEvent::GaveUp { attempt });
+                    return Err(error);
+                }
+                Err(error) => {
+                    log::error!("Failed to reconnect: {error}");
+                    let _ = self.events.send(ReconnectEvent::Failed { attempt, error });
+                }
+            }
         }
+    }
+
+    async fn connect(&self) -> Result<(WebSocketStream, SessionWelcome)> {
+        let (ws_stream, welcome) = connect_session(self.url.clone()).await?;
+        (self.resubscribe)(welcome.session.id.clone()).await?;
+        Ok((ws_stream, welcome))
+    }
+}
 
-        Ok(Client {
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
+)]
+pub struct Client {
+    inner_stream: Pin<Box<WebSocketStream>>,
+    reconnect: Option<tokio::task::JoinHandle<Result<(WebSocketStream, SessionWelcome)>>>,
+    next_stream: Option<Pin<Box<WebSocketStream>>>,
+    ping_sleep: Pin<Box<tokio::time::Sleep>>,
+    keepalive_sleep: Pin<Box<tokio::time::Sleep>>,
+    keepalive_timeout: Option<tokio::time::Duration>,
+    resilient: Option<Resilient>,
+    resuming: bool,
+    welcome: Option<SessionWelcome>,
+    pub session_id: String,
+    pub last_error: Option<Error>,
+    pub deduplicator: Deduplicator,
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum SessionEvent {
+    Welcome(SessionWelcome),
+    Keepalive,
+    Reconnect(SessionWelcome),
+    Notification(Envelope),
+    Closed { code: Option<u16>, reason: String },
+    Malformed(Malformed),
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
+)]
+pub struct Events(pub Client);
+
+impl Stream for Events {
+    type Item = SessionEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_event(cx)
+    }
+}
+
+//Yields notifications with their metadata, messages that could not be parsed at all come out as Err.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
+)]
+pub struct Envelopes(pub Client);
+
+impl Stream for Envelopes {
+    type Item = std::result::Result<Envelope, Malformed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_envelope(cx)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
+)]
+pub struct Chat(pub Client);
+
+impl Stream for Chat {
+    type Item = ChatEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .0
+            .poll_chat_envelope(cx)
+            .map(|v| v.map(|v| v.map_or_else(ChatEvent::Malformed, |v| v.event)))
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(bevy_ecs::system::Resource, bevy_ecs::component::Component)
+)]
+pub struct ChatEnvelopes(pub Client);
+
+impl Stream for ChatEnvelopes {
+    type Item = std::result::Result<ChatEnvelope, Malformed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_chat_envelope(cx)
+    }
+}
+
+impl Stream for Client {
+    type Item = NotificationType;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_envelope(cx)
+            .map(|v| v.map(into_notification))
+    }
+}
+
+fn into_notification(envelope: std::result::Result<Envelope, Malformed>) -> NotificationType {
+    envelope.map_or_else(NotificationType::Malformed, |v| v.event)
+}
+
+fn chat_event(event: NotificationType) -> Option<ChatEvent> {
+    Some(match event {
+        NotificationType::ChannelChatMessage(v) => ChatEvent::Message(v),
+        NotificationType::ChannelChatNotification(v) => ChatEvent::Notification(v),
+        NotificationType::ChannelChatClear(v) => ChatEvent::Clear(v),
+        NotificationType::ChannelChatClearUserMessages(v) => ChatEvent::ClearUserMessages(v),
+        NotificationType::ChannelChatMessageDelete(v) => ChatEvent::MessageDelete(v),
+        NotificationType::ChannelChatSettingsUpdate(v) => ChatEvent::SettingsUpdate(v),
+        NotificationType::Revocation(v) => ChatEvent::Revocation(v),
+        NotificationType::Malformed(v) => ChatEvent::Malformed(v),
+        _ => return None,
+    })
+}
+
+type Parser = fn(serde_json::Value) -> serde_json::Result<NotificationType>;
+
+macro_rules! parsers {
+    ($($name:literal => $variant:ident,)*) => {
+        const PARSERS: &[(&str, Parser)] = &[
+            $(($name, |v| serde_json::from_value(v).map(NotificationType::$variant)),)*
+        ];
+    };
+}
+
+parsers! {
+    "channel.update" => ChannelUpdate,
+    "channel.follow" => ChannelFollow,
+    "channel.channel_points_custom_reward_redemption.add" => CustomRewardRedemptionAdd,
+    "channel.channel_points_custom_reward_redemption.update" => CustomRewardRedemptionUpdate,
+    "channel.channel_points_custom_reward.add" => CustomRewardAdd,
+    "channel.channel_points_custom_reward.update" => CustomRewardUpdate,
+    "channel.channel_points_custom_reward.remove" => CustomRewardRemove,
+    "channel.subscribe" => ChannelSubscribe,
+    "channel.subscription.end" => ChannelSubscriptionEnd,
+    "channel.subscription.gift" => ChannelSubscriptionGift,
+    "channel.subscription.message" => ChannelSubscriptionMessage,
+    "channel.cheer" => ChannelCheer,
+    "channel.raid" => ChannelRaid,
+    "channel.ban" => ChannelBan,
+    "channel.unban" => ChannelUnban,
+    "channel.moderator.add" => ChannelModeratorAdd,
+    "channel.moderator.remove" => ChannelModeratorRemove,
+    "channel.poll.begin" => ChannelPollBegin,
+    "channel.poll.progress" => ChannelPollProgress,
+    "channel.poll.end" => ChannelPollEnd,
+    "channel.prediction.begin" => ChannelPredictionBegin,
+    "channel.prediction.progress" => ChannelPredictionProgress,
+    "channel.prediction.lock" => ChannelPredictionLock,
+    "channel.prediction.end" => ChannelPredictionEnd,
+    "channel.hype_train.begin" => ChannelHypeTrainBegin,
+    "channel.hype_train.progress" => ChannelHypeTrainProgress,
+    "channel.hype_train.end" => ChannelHypeTrainEnd,
+    "channel.chat.message" => ChannelChatMessage,
+    "channel.chat.notification" => ChannelChatNotification,
+    "channel.chat.clear" => ChannelChatClear,
+    "channel.chat.clear_user_messages" => ChannelChatClearUserMessages,
+    "channel.chat.message_delete" => ChannelChatMessageDelete,
+    "channel.chat_settings.update" => ChannelChatSettingsUpdate,
+    "stream.online" => StreamOnline,
+    "stream.offline" => StreamOffline,
+}
+
+//Handles both notification and revocation messages. Only fails when the message itself is
+//unusable, events that don't match their type end up as NotificationType::Malformed.
+pub(crate) fn parse_envelope(
+    metadata: MessageMetadata,
+    payload: serde_json::Value,
+) -> std::result::Result<Envelope, Malformed> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(&metadata.message_timestamp)
+        .map_err(|e| {
+            format!(
+                "Invalid message_timestamp {0}: {e}",
+                metadata.message_timestamp
+            )
+        })
+        .and_then(|timestamp| {
+            let subscription = crate::helix::EventSub::deserialize(&payload["subscription"])
+                .map_err(|e| format!("Invalid subscription: {e}"))?;
+            Ok((timestamp.with_timezone(&chrono::Utc), subscription))
+        });
+    let (timestamp, subscription) = match parsed {
+        Ok(v) => v,
+        Err(error) => return Err(Malformed { payload, error }),
+    };
+
+    let event = match metadata.message_type.as_str() {
+        "revocation" => NotificationType::Revocation(Revocation {
+            subscription: subscription.clone(),
+        }),
+        _ => {
+            let subtype = metadata
+                .subscription_type
+                .as_deref()
+                .unwrap_or(&subscription.r#type);
+            parse_notification(subtype, payload)
+        }
+    };
+
+    Ok(Envelope {
+        metadata,
+        timestamp,
+        subscription,
+        event,
+    })
+}
+
+pub(crate) fn parse_notification(subtype: &str, payload: serde_json::Value) -> NotificationType {
+    let parsed = serde_json::from_value::<Notification>(payload.clone()).and_then(|notification| {
+        match PARSERS.iter().find(|(name, _)| *name == subtype) {
+            Some((_, parse)) => parse(notification.event).map(Some),
+            None => Ok(None),
+        }
+    });
+
+    match parsed {
+        Ok(Some(v)) => v,
+        Ok(None) => NotificationType::Other(payload),
+        Err(e) => {
+            log::error!("Failed to parse {subtype} payload: {e}");
+            NotificationType::Malformed(Malformed {
+                payload,
+                error: e.to_string(),
+            })
+        }
+    }
+}
+
+impl Client {
+    fn new(
+        ws_stream: WebSocketStream,
+        welcome: SessionWelcome,
+        resilient: Option<Resilient>,
+    ) -> Client {
+        let keepalive_timeout = keepalive_timeout(&welcome);
+        Client {
             inner_stream: Pin::new(Box::new(ws_stream)),
+            reconnect: None,
+            next_stream: None,
             ping_sleep: Box::pin(tokio::time::sleep(tokio::time::Duration::from_secs(30))),
-            session_id: welcome.session.id,
+            keepalive_sleep: Box::pin(tokio::time::sleep(keepalive_timeout.unwrap_or_default())),
+            keepalive_timeout,
+            resilient,
+            resuming: false,
+            session_id: welcome.session.id.clone(),
+            welcome: Some(welcome),
             last_error: None,
-        })
+            deduplicator: Deduplicator::default(),
+        }
+    }
+
+    pub fn into_events(self) -> Events {
+        Events(self)
+    }
+
+    pub fn into_chat(self) -> Chat {
+        Chat(self)
+    }
+
+    pub fn into_envelopes(self) -> Envelopes {
+        Envelopes(self)
+    }
+
+    pub fn into_chat_envelopes(self) -> ChatEnvelopes {
+        ChatEnvelopes(self)
+    }
+
+    //Every notification stream is built on this, the others only map or filter what it yields.
+    fn poll_envelope(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Envelope, Malformed>>> {
+        loop {
+            match self.poll_event(cx) {
+                Poll::Ready(Some(SessionEvent::Notification(v))) => {
+                    return Poll::Ready(Some(Ok(v)))
+                }
+                Poll::Ready(Some(SessionEvent::Malformed(v))) => return Poll::Ready(Some(Err(v))),
+                Poll::Ready(Some(..)) => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_chat_envelope(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<ChatEnvelope, Malformed>>> {
+        loop {
+            let envelope = match self.poll_envelope(cx) {
+                Poll::Ready(Some(Ok(v))) => v,
+                Poll::Ready(Some(Err(v))) => return Poll::Ready(Some(Err(v))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(event) = chat_event(envelope.event) {
+                return Poll::Ready(Some(Ok(ChatEnvelope {
+                    metadata: envelope.metadata,
+                    timestamp: envelope.timestamp,
+                    subscription: envelope.subscription,
+                    event,
+                })));
+            }
+        }
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<SessionEvent>> {
+        if let Some(reconnect) = self.reconnect.as_mut() {
+            match Pin::new(reconnect).poll(cx) {
+                Poll::Pending => {}
+                Poll::Ready(v) => {
+                    self.reconnect = None;
+                    match v.unwrap_or(Err(Error::ConnectionClosed)) {
+                        Ok((ws_stream, welcome)) => {
+                            log::debug!("Reconnected EventSub session {}", welcome.session.id);
+                            self.keepalive_timeout = keepalive_timeout(&welcome);
+                            self.session_id = welcome.session.id.clone();
+                            self.welcome = Some(welcome);
+                            if self.resuming {
+                                self.resuming = false;
+                                self.inner_stream = Box::pin(ws_stream);
+                                self.reset_keepalive();
+                            } else {
+                                //Twitch closes the old connection once the new one is welcomed,
+                                //drain it before switching over so no notification is lost.
+                                self.next_stream = Some(Box::pin(ws_stream));
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to reconnect: {e}");
+                            self.last_error.replace(e);
+                            if self.resuming {
+                                //Resilient::resume only gives up on errors that retrying won't fix.
+                                self.resuming = false;
+                                self.resilient = None;
+                                return Poll::Ready(None);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(welcome) = self.welcome.take() {
+            return Poll::Ready(Some(SessionEvent::Welcome(welcome)));
+        }
+
+        //The old connection is gone, neither ping it nor time it out while resuming.
+        if self.resuming {
+            return Poll::Pending;
+        }
+
+        match self.ping_sleep.as_mut().poll(cx) {
+            Poll::Pending => {}
+            Poll::Ready(..) => {
+                self.ping_sleep
+                    .as_mut()
+                    .reset(tokio::time::Instant::now() + tokio::time::Duration::from_secs(30));
+
+                if let Err(e) = self.inner_stream.as_mut().start_send(
+                    tokio_tungstenite::tungstenite::protocol::Message::Ping(vec![]),
+                ) {
+                    log::warn!("Failed to send ping: {e}");
+                    self.last_error.replace(e.into());
+                    return self.end(cx);
+                };
+            }
+        };
+
+        loop {
+            let v = match self.inner_stream.as_mut().poll_next(cx) {
+                Poll::Pending => {
+                    if self.reconnect.is_some() {
+                        return Poll::Pending;
+                    }
+
+                    //Only time out when there is nothing left to read.
+                    let timeout = match self.keepalive_timeout {
+                        Some(v) => v,
+                        None => return Poll::Pending,
+                    };
+                    match self.keepalive_sleep.as_mut().poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(..) if self.next_stream.is_some() => {
+                            //The old connection went quiet instead of closing, move on to the new one.
+                            log::debug!("No message received within {timeout:?}, switching over");
+                            if let Some(next_stream) = self.next_stream.take() {
+                                self.inner_stream = next_stream;
+                            }
+                            self.reset_keepalive();
+                            continue;
+                        }
+                        Poll::Ready(..) => {
+                            log::error!("No message received within {timeout:?}");
+                            self.last_error.replace(Error::KeepaliveTimeout(timeout));
+                            break;
+                        }
+                    }
+                }
+                Poll::Ready(v) => v,
+            };
+
+            if let Some(Ok(..)) = &v {
+                self.reset_keepalive();
+            }
+
+            match v {
+                Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Ping(..))) => {
+                    if let Err(e) = self.inner_stream.as_mut().start_send(
+                        tokio_tungstenite::tungstenite::protocol::Message::Pong(vec![]),
+                    ) {
+                        log::error!("Failed to send pong: {e}");
+                        self.last_error.replace(e.into());
+                        break;
+                    }
+                }
+                Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Text(text))) => {
+                    let message: Message = match serde_json::from_str(&text) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            log::error!("Failed to parse message: {e}");
+                            return Poll::Ready(Some(SessionEvent::Malformed(Malformed {
+                                payload: serde_json::Value::String(text),
+                                error: e.to_string(),
+                            })));
+                        }
+                    };
+
+                    match message.metadata.message_type.as_str() {
+                        "notification" | "revocation" => {
+                            let message_type = message.metadata.message_type.clone();
+                            let envelope = match parse_envelope(message.metadata, message.payload) {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    log::error!("Failed to parse {message_type}: {0}", e.error);
+                                    return Poll::Ready(Some(SessionEvent::Malformed(e)));
+                                }
+                            };
+
+                            //Only parsed messages are recorded, so a redelivery can fix a bad one.
+                            let metadata = &envelope.metadata;
+                            if !self
+                                .deduplicator
+                                .check(&metadata.message_id, &metadata.message_timestamp)
+                            {
+                                continue;
+                            }
+
+                            if let NotificationType::Revocation(revocation) = &envelope.event {
+                                log::warn!(
+                                    "Subscription {} ({}) revoked: {}",
+                                    revocation.subscription.id,
+                                    revocation.subscription.r#type,
+                                    revocation.subscription.status
+                                );
+                            }
+                            return Poll::Ready(Some(SessionEvent::Notification(envelope)));
+                        }
+                        "session_keepalive" => return Poll::Ready(Some(SessionEvent::Keepalive)),
+                        "session_reconnect" => {
+                            //Without a usable reconnect_url Twitch closes the connection after a
+                            //while, which ends or resumes the session as usual.
+                            let parsed = SessionWelcome::deserialize(&message.payload)
+                                .map_err(|e| e.to_string())
+                                .and_then(|reconnect| {
+                                    match reconnect.session.reconnect_url.clone() {
+                                        Some(url) => Ok((reconnect, url)),
+                                        None => Err("Missing reconnect_url".to_string()),
+                                    }
+                                });
+                            let (reconnect, url) = match parsed {
+                                Ok(v) => v,
+                                Err(error) => {
+                                    log::error!("Failed to parse session_reconnect: {error}");
+                                    return Poll::Ready(Some(SessionEvent::Malformed(Malformed {
+                                        payload: message.payload,
+                                        error,
+                                    })));
+                                }
+                            };
+
+                            //Keep reading the old connection until the new one is welcomed.
+                            if let Some(reconnect) = self.reconnect.take() {
+                                reconnect.abort();
+                            }
+                            self.next_stream = None;
+                            self.resuming = false;
+                            self.reconnect = Some(tokio::spawn(connect_session(url)));
+                            return Poll::Ready(Some(SessionEvent::Reconnect(reconnect)));
+                        }
+                        _ => {}
+                    }
+                }
+                Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Close(frame))) => {
+                    let (code, reason) = match frame {
+                        Some(v) => (Some(u16::from(v.code)), v.reason.to_string()),
+                        None => (None, String::new()),
+                    };
+                    log::debug!("EventSub connection closed: {code:?} {reason}");
+                    return Poll::Ready(Some(SessionEvent::Closed { code, reason }));
+                }
+                Some(Ok(r)) => {
+                    log::trace!("Unknown message: {r:?}");
+                }
+                Some(Err(..)) | None if self.reconnect.is_some() || self.next_stream.is_some() => {
+                    match self.next_stream.take() {
+                        Some(next_stream) => {
+                            self.inner_stream = next_stream;
+                            self.reset_keepalive();
+                        }
+                        None => return Poll::Pending,
+                    }
+                }
+                Some(Err(e)) => {
+                    log::error!("Failed to receive message: {e}");
+                    self.last_error.replace(e.into());
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        self.end(cx)
+    }
+
+    fn reset_keepalive(&mut self) {
+        if let Some(timeout) = self.keepalive_timeout {
+            self.keepalive_sleep
+                .as_mut()
+                .reset(tokio::time::Instant::now() + timeout);
+        }
+    }
+
+    fn end(&mut self, cx: &mut Context<'_>) -> Poll<Option<SessionEvent>> {
+        //Restarting a running resume would reset its backoff.
+        if self.resuming && self.reconnect.is_some() {
+            return Poll::Pending;
+        }
+
+        let resilient = match &self.resilient {
+            Some(v) => v.clone(),
+            None => return Poll::Ready(None),
+        };
+
+        if let Some(reconnect) = self.reconnect.take() {
+            reconnect.abort();
+        }
+        let error = self.last_error.take().unwrap_or(Error::ConnectionClosed);
+        self.reconnect = Some(tokio::spawn(resilient.resume(error)));
+        self.next_stream = None;
+        self.resuming = true;
+
+        self.poll_event(cx)
+    }
+}
+
+#[derive(Debug)]
+pub struct ShardManager {
+    pub conduit_id: String,
+    shards: futures::stream::SelectAll<Envelopes>,
+}
+
+impl ShardManager {
+    pub fn session_ids(&self) -> Vec<String> {
+        self.shards.iter().map(|c| c.0.session_id.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+}
+
+impl ShardManager {
+    pub fn into_envelopes(self) -> ShardEnvelopes {
+        ShardEnvelopes(self)
+    }
+}
+
+impl Stream for ShardManager {
+    type Item = NotificationType;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .shards
+            .poll_next_unpin(cx)
+            .map(|v| v.map(into_notification))
+    }
+}
+
+#[derive(Debug)]
+pub struct ShardEnvelopes(pub ShardManager);
+
+impl Stream for ShardEnvelopes {
+    type Item = std::result::Result<Envelope, Malformed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.shards.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(reconnect) = &self.reconnect {
+            reconnect.abort();
+        }
+    }
+}
+
+fn keepalive_timeout(welcome: &SessionWelcome) -> Option<tokio::time::Duration> {
+    let seconds = welcome.session.keepalive_timeout_seconds?;
+    Some(tokio::time::Duration::from_secs(seconds.try_into().ok()?))
+}
+
+async fn connect_session(url: String) -> Result<(WebSocketStream, SessionWelcome)> {
+    let (mut ws_stream, _) = match tokio_tungstenite::connect_async(&url).await {
+        Ok(v) => v,
+        Err(e) => return Err(e.into()),
+    };
+
+    let welcome = loop {
+        let msg = ws_stream.next().await;
+        match msg {
+            Some(Ok(tokio_tungstenite::tungstenite::protocol::Message::Text(text))) => {
+                let message: Message = match serde_json::from_str(&text) {
+                    Ok(v) => v,
+                    Err(source) => return Err(Error::Deserialize { source, body: text }),
+                };
+
+                if message.metadata.message_type.as_str() != "session_welcome" {
+                    return Err(Error::UnexpectedMessage(message.metadata.message_type));
+                }
+
+                let welcome: SessionWelcome = match serde_json::from_value(message.payload.clone())
+                {
+                    Ok(v) => v,
+                    Err(source) => return Err(Error::Deserialize { source, body: text }),
+                };
+
+                break welcome;
+            }
+            Some(Err(e)) => return Err(e.into()),
+            Some(..) => {}
+            None => return Err(Error::ConnectionClosed),
+        }
+    };
+
+    Ok((ws_stream, welcome))
+}
+
+impl<T: crate::auth::TokenStorage> crate::helix::Client<T> {
+    pub async fn check_eventsub_scopes(&self, subscriptions: &[Subscription]) -> Result<()> {
+        let scopes = self.get_token_scopes().await?;
+        for subscription in subscriptions {
+            let subscription_type: SubscriptionType = subscription.r#type.parse()?;
+
+            let missing = subscription_type.missing_scopes(&scopes);
+            if !missing.is_empty() {
+                return Err(Error::MissingScopes {
+                    subscription_type: subscription.r#type.clone(),
+                    scopes: missing,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn subscribe_eventsub(
+        &self,
+        session_id: &str,
+        templates: &[crate::helix::EventSubCreate],
+    ) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for template in templates {
+            let mut create = template.clone();
+            create.transport.session_id = Some(session_id.to_string());
+            ids.push(self.create_eventsub_subscription(&create).await?.id);
+        }
+        Ok(ids)
+    }
+
+    pub async fn connect_eventsub(&self, subscriptions: Vec<Subscription>) -> Result<Client> {
+        self.check_eventsub_scopes(&subscriptions).await?;
+        let templates: Vec<_> = subscriptions
+            .into_iter()
+            .map(Subscription::into_create)
+            .collect();
+        let (ws_stream, welcome) = connect_session(self.eventsub_url.clone()).await?;
+        self.subscribe_eventsub(&welcome.session.id, &templates)
+            .await?;
+
+        Ok(Client::new(ws_stream, welcome, None))
+    }
+
+    pub async fn connect_conduit(
+        &self,
+        conduit_id: String,
+        shard_count: usize,
+    ) -> Result<(
+        ShardManager,
+        tokio::sync::mpsc::UnboundedReceiver<ReconnectEvent>,
+    )>
+    where
+        T: Send + Sync + 'static,
+    {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut clients = Vec::new();
+        let mut shards = Vec::new();
+        for id in 0..shard_count {
+            let (ws_stream, welcome) = connect_session(self.eventsub_url.clone()).await?;
+            shards.push(ConduitShardUpdate {
+                id: id.to_string(),
+                transport: EventSubTransport::websocket(welcome.session.id.clone()),
+            });
+
+            //A resumed shard points its id at the new session, so the conduit keeps delivering to it.
+            let client = self.clone();
+            let conduit_id = conduit_id.clone();
+            let resubscribe: Resubscribe = Arc::new(move |session_id: String| {
+                let client = client.clone();
+                let conduit_id = conduit_id.clone();
+                let shard = ConduitShardUpdate {
+                    id: id.to_string(),
+                    transport: EventSubTransport::websocket(session_id),
+                };
+                Box::pin(async move {
+                    let updated = client
+                        .update_conduit_shards(conduit_id, vec![shard])
+                        .await?;
+                    if !updated.errors.is_empty() {
+                        return Err(Error::ConduitShards(updated.errors));
+                    }
+                    Ok(())
+                })
+            });
+
+            let resilient = Resilient {
+                url: self.eventsub_url.clone(),
+                resubscribe,
+                events: sender.clone(),
+            };
+            clients.push(Client::new(ws_stream, welcome, Some(resilient)).into_envelopes());
+        }
+
+        let updated = self
+            .update_conduit_shards(conduit_id.clone(), shards)
+            .await?;
+        if !updated.errors.is_empty() {
+            return Err(Error::ConduitShards(updated.errors));
+        }
+
+        Ok((
+            ShardManager {
+                conduit_id,
+                shards: futures::stream::select_all(clients),
+            },
+            receiver,
+        ))
+    }
+
+    pub async fn connect_eventsub_resilient(
+        &self,
+        subscriptions: Vec<Subscription>,
+    ) -> Result<(Client, tokio::sync::mpsc::UnboundedReceiver<ReconnectEvent>)>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.check_eventsub_scopes(&subscriptions).await?;
+        let templates: Vec<_> = subscriptions
+            .into_iter()
+            .map(Subscription::into_create)
+            .collect();
+        let templates = Arc::new(templates);
+        let (ws_stream, welcome) = connect_session(self.eventsub_url.clone()).await?;
+        let subscriptions = Arc::new(Mutex::new(
+            self.subscribe_eventsub(&welcome.session.id, &templates)
+                .await?,
+        ));
+
+        let client = self.clone();
+        let resubscribe: Resubscribe = Arc::new(move |session_id: String| {
+            let client = client.clone();
+            let templates = templates.clone();
+            let subscriptions = subscriptions.clone();
+            Box::pin(async move {
+                let stale: Vec<String> = subscriptions.lock().unwrap().drain(..).collect();
+                for id in stale {
+                    if let Err(e) = client.delete_eventsub_subscription(id.clone()).await {
+                        log::warn!("Failed to delete stale subscription {id}: {e}");
+                    }
+                }
+
+                //Track ids one by one so a partial failure is cleaned up on the next attempt.
+                for template in templates.iter() {
+                    let ids = client
+                        .subscribe_eventsub(&session_id, std::slice::from_ref(template))
+                        .await?;
+                    subscriptions.lock().unwrap().extend(ids);
+                }
+                Ok(())
+            })
+        });
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let resilient = Resilient {
+            url: self.eventsub_url.clone(),
+            resubscribe,
+            events: sender,
+        };
+
+        Ok((Client::new(ws_stream, welcome, Some(resilient)), receiver))
     }
 }
@@ -1,14 +1,26 @@
-use crate::auth::{Token, TokenStorage, TokenType};
-use anyhow::bail;
-use anyhow::Result;
+use crate::auth::{Token, TokenStorage, TokenType, VoidStorage};
+use crate::error::{Error, Result, TwitchError};
+use crate::ratelimit::{RateLimit, RateLimiter};
+use chrono::Utc;
 
+use futures::TryStreamExt;
 use reqwest::Client as HttpClient;
-use reqwest::{Method, Response};
+use reqwest::{Method, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
 
 #[cfg(feature = "bevy")]
 use bevy_ecs::prelude::{Component, Resource};
 
+pub const HELIX_URL: &str = "https://api.twitch.tv/helix";
+pub const OAUTH_URL: &str = "https://id.twitch.tv/oauth2";
+pub const EVENTSUB_URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+
+const RATE_LIMIT_RETRIES: u32 = 3;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TwitchData<T> {
     #[serde(default = "Vec::new")]
@@ -16,19 +28,151 @@ pub struct TwitchData<T> {
     pub error: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwitchPage<T> {
+    #[serde(default = "Vec::new")]
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub pagination: Pagination,
+    pub total: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Pagination {
     pub cursor: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 #[cfg_attr(feature = "bevy", derive(Resource, Component))]
 pub struct Client<T: TokenStorage> {
     pub client_id: String,
     pub client_secret: String,
-    pub token: Token,
+    pub token: Arc<RwLock<Token>>,
     pub http_client: HttpClient,
-    pub token_storage: T,
+    pub token_storage: Arc<Mutex<T>>,
+    pub helix_url: String,
+    pub oauth_url: String,
+    pub eventsub_url: String,
+    pub timeout: Duration,
+    pub user_agent: Option<String>,
+    pub(crate) rate_limiter: RateLimiter,
+}
+
+impl<T: TokenStorage> Clone for Client<T> {
+    fn clone(&self) -> Self {
+        Client {
+            client_id: self.client_id.clone(),
+            client_secret: self.client_secret.clone(),
+            token: self.token.clone(),
+            http_client: self.http_client.clone(),
+            token_storage: self.token_storage.clone(),
+            helix_url: self.helix_url.clone(),
+            oauth_url: self.oauth_url.clone(),
+            eventsub_url: self.eventsub_url.clone(),
+            timeout: self.timeout,
+            user_agent: self.user_agent.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientBuilder<T: TokenStorage = VoidStorage> {
+    client_id: String,
+    client_secret: String,
+    token: Token,
+    token_storage: T,
+    http_client: Option<HttpClient>,
+    helix_url: String,
+    oauth_url: String,
+    eventsub_url: String,
+    timeout: Duration,
+    user_agent: Option<String>,
+}
+
+impl ClientBuilder<VoidStorage> {
+    pub fn new(client_id: String, client_secret: String) -> ClientBuilder<VoidStorage> {
+        ClientBuilder {
+            client_id,
+            client_secret,
+            token: Token::default(),
+            token_storage: VoidStorage {},
+            http_client: None,
+            helix_url: HELIX_URL.to_string(),
+            oauth_url: OAUTH_URL.to_string(),
+            eventsub_url: EVENTSUB_URL.to_string(),
+            timeout: Duration::from_secs(5),
+            user_agent: None,
+        }
+    }
+}
+
+impl<T: TokenStorage> ClientBuilder<T> {
+    pub fn token(mut self, token: Token) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn token_storage<T2: TokenStorage>(self, token_storage: T2) -> ClientBuilder<T2> {
+        ClientBuilder {
+            client_id: self.client_id,
+            client_secret: self.client_secret,
+            token: self.token,
+            token_storage,
+            http_client: self.http_client,
+            helix_url: self.helix_url,
+            oauth_url: self.oauth_url,
+            eventsub_url: self.eventsub_url,
+            timeout: self.timeout,
+            user_agent: self.user_agent,
+        }
+    }
+
+    pub fn http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    pub fn helix_url(mut self, helix_url: String) -> Self {
+        self.helix_url = helix_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn oauth_url(mut self, oauth_url: String) -> Self {
+        self.oauth_url = oauth_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn eventsub_url(mut self, eventsub_url: String) -> Self {
+        self.eventsub_url = eventsub_url;
+        self
+    }
+
+    pub fn build(self) -> Client<T> {
+        Client {
+            client_id: self.client_id,
+            client_secret: self.client_secret,
+            token: Arc::new(RwLock::new(self.token)),
+            http_client: self.http_client.unwrap_or_default(),
+            token_storage: Arc::new(Mutex::new(self.token_storage)),
+            helix_url: self.helix_url,
+            oauth_url: self.oauth_url,
+            eventsub_url: self.eventsub_url,
+            timeout: self.timeout,
+            user_agent: self.user_agent,
+            rate_limiter: Default::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -141,7 +285,36 @@ pub struct EventSubTransport {
     pub callback: Option<String>,
     pub secret: Option<String>,
     pub session_id: Option<String>,
+    pub conduit_id: Option<String>,
     pub connected_at: Option<String>,
+    pub disconnected_at: Option<String>,
+}
+
+impl EventSubTransport {
+    pub fn websocket(session_id: String) -> EventSubTransport {
+        EventSubTransport {
+            method: "websocket".to_string(),
+            session_id: Some(session_id),
+            ..Default::default()
+        }
+    }
+
+    pub fn webhook(callback: String, secret: String) -> EventSubTransport {
+        EventSubTransport {
+            method: "webhook".to_string(),
+            callback: Some(callback),
+            secret: Some(secret),
+            ..Default::default()
+        }
+    }
+
+    pub fn conduit(conduit_id: String) -> EventSubTransport {
+        EventSubTransport {
+            method: "conduit".to_string(),
+            conduit_id: Some(conduit_id),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -153,6 +326,12 @@ pub struct EventSubCondition {
     pub from_broadcaster_user_id: Option<String>,
     pub to_broadcaster_user_id: Option<String>,
     pub reward_id: Option<String>,
+    pub client_id: Option<String>,
+    pub conduit_id: Option<String>,
+    pub organization_id: Option<String>,
+    pub category_id: Option<String>,
+    pub campaign_id: Option<String>,
+    pub extension_client_id: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -175,6 +354,89 @@ pub struct EventSubCreate {
     pub transport: EventSubTransport,
 }
 
+impl EventSubCreate {
+    pub fn new(
+        r#type: crate::eventsub::SubscriptionType,
+        condition: EventSubCondition,
+        transport: EventSubTransport,
+    ) -> EventSubCreate {
+        EventSubCreate {
+            r#type: r#type.as_str().to_string(),
+            version: r#type.version().to_string(),
+            condition,
+            transport,
+        }
+    }
+}
+
+//Helix accepts at most one of these filters per request.
+#[derive(Debug, Clone)]
+pub enum EventSubFilter {
+    Status(String),
+    Type(String),
+    UserId(String),
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EventSubPage {
+    pub data: Vec<EventSub>,
+    #[serde(default)]
+    pub pagination: Pagination,
+    pub total: i64,
+    pub total_cost: i64,
+    pub max_total_cost: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Conduit {
+    pub id: String,
+    pub shard_count: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitCreate {
+    pub shard_count: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitUpdate {
+    pub id: String,
+    pub shard_count: i64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitShard {
+    pub id: String,
+    pub status: String,
+    pub transport: EventSubTransport,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitShardUpdate {
+    pub id: String,
+    pub transport: EventSubTransport,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitShardsUpdate {
+    pub conduit_id: String,
+    pub shards: Vec<ConduitShardUpdate>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitShardError {
+    pub id: String,
+    pub message: String,
+    pub code: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConduitShardsUpdated {
+    pub data: Vec<ConduitShard>,
+    #[serde(default)]
+    pub errors: Vec<ConduitShardError>,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BanUser {
     pub user_id: String,
@@ -320,6 +582,7 @@ pub struct ChannelFollowers {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChannelFollowersData {
     pub data: Vec<ChannelFollowers>,
+    #[serde(default)]
     pub pagination: Pagination,
     pub total: i64,
 }
@@ -359,11 +622,24 @@ pub struct Video {
 
 impl<T: TokenStorage> Client<T> {
     pub async fn http_request<T2: serde::Serialize>(
-        &mut self,
+        &self,
         method: Method,
         uri: String,
         data_json: Option<T2>,
         data_form: Option<String>,
+    ) -> Result<Response> {
+        let access_token = self.token.read().await.access_token.clone();
+        self.send_request(method, uri, data_json, data_form, &access_token)
+            .await
+    }
+
+    async fn send_request<T2: serde::Serialize>(
+        &self,
+        method: Method,
+        uri: String,
+        data_json: Option<T2>,
+        data_form: Option<String>,
+        access_token: &str,
     ) -> Result<Response> {
         let mut req = self.http_client.request(method, uri);
 
@@ -375,47 +651,102 @@ impl<T: TokenStorage> Client<T> {
             },
         };
 
+        if let Some(user_agent) = &self.user_agent {
+            req = req.header("User-Agent", user_agent);
+        }
+
         let req = req
-            .timeout(core::time::Duration::from_secs(5))
-            .header(
-                "Authorization",
-                format!("Bearer {0}", self.token.access_token),
-            )
+            .timeout(self.timeout)
+            .header("Authorization", format!("Bearer {access_token}"))
             .header("Client-Id", self.client_id.clone());
 
-        Ok(req.send().await?)
+        self.rate_limiter.acquire().await;
+        let res = req.send().await?;
+        self.rate_limiter.update(res.headers());
+
+        Ok(res)
     }
 
     pub async fn request<T1: serde::Serialize + std::clone::Clone>(
-        &mut self,
+        &self,
         method: Method,
         uri: String,
         data_json: Option<T1>,
         data_form: Option<String>,
     ) -> Result<Response> {
+        let access_token = self.token.read().await.access_token.clone();
         let mut res = self
-            .http_request(
+            .send_request(
                 method.clone(),
                 uri.clone(),
                 data_json.clone(),
                 data_form.clone(),
+                &access_token,
             )
             .await?;
 
         if res.status() == reqwest::StatusCode::UNAUTHORIZED {
             //Token invalid, get new? If fail, or fail again, return error.
-            self.refresh_token().await?;
-            res = self.http_request(method, uri, data_json, data_form).await?;
+            self.refresh_expired_token(&access_token)
+                .await
+                .map_err(|e| Error::TokenRefresh(Box::new(e)))?;
+            res = self
+                .http_request(
+                    method.clone(),
+                    uri.clone(),
+                    data_json.clone(),
+                    data_form.clone(),
+                )
+                .await?;
+        }
+
+        let mut retries = 0;
+        while res.status() == StatusCode::TOO_MANY_REQUESTS && retries < RATE_LIMIT_RETRIES {
+            retries += 1;
+            log::warn!("Rate limited, retrying after reset ({retries}/{RATE_LIMIT_RETRIES})");
+            self.rate_limiter.exhaust();
+            res = self
+                .http_request(
+                    method.clone(),
+                    uri.clone(),
+                    data_json.clone(),
+                    data_form.clone(),
+                )
+                .await?;
         }
 
         Ok(res)
     }
 
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limiter.get()
+    }
+
+    pub(crate) async fn error_for_status(res: Response) -> Result<Response> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let reset = RateLimit::from_headers(res.headers())
+                .map(|r| r.reset)
+                .unwrap_or_else(Utc::now);
+            return Err(Error::RateLimited { reset });
+        }
+
+        let body = res.text().await?;
+        match serde_json::from_str::<TwitchError>(&body) {
+            Ok(error) => Err(Error::Twitch(error)),
+            Err(..) => Err(Error::Status { status, body }),
+        }
+    }
+
     pub async fn request_result<
         T1: for<'de> serde::Deserialize<'de>,
         T2: serde::Serialize + std::clone::Clone,
     >(
-        &mut self,
+        &self,
         method: Method,
         uri: String,
         data_json: Option<T2>,
@@ -424,305 +755,530 @@ impl<T: TokenStorage> Client<T> {
         let res = self
             .request::<T2>(method, uri, data_json, data_form)
             .await?;
-        Ok(res.json::<T1>().await?)
+        let res = Self::error_for_status(res).await?;
+        let body = res.text().await?;
+        serde_json::from_str::<T1>(&body).map_err(|source| Error::Deserialize { source, body })
     }
 
-    pub async fn get<T1: for<'de> serde::Deserialize<'de>>(&mut self, uri: String) -> Result<T1> {
-        return self
-            .request_result::<T1, String>(Method::GET, uri, None, None)
-            .await;
+    pub async fn get<T1: for<'de> serde::Deserialize<'de>>(&self, uri: String) -> Result<T1> {
+        self.request_result::<T1, String>(Method::GET, uri, None, None)
+            .await
     }
 
-    pub async fn post_empty(&mut self, uri: String) -> Result<()> {
-        match self.request::<String>(Method::POST, uri, None, None).await {
-            Ok(..) => Ok(()),
-            Err(e) => Err(e),
-        }
+    pub async fn post_empty(&self, uri: String) -> Result<()> {
+        let res = self
+            .request::<String>(Method::POST, uri, None, None)
+            .await?;
+        Self::error_for_status(res).await?;
+        Ok(())
     }
 
     pub async fn post_form<T1: for<'de> serde::Deserialize<'de>>(
-        &mut self,
+        &self,
         uri: String,
         data: String,
     ) -> Result<T1> {
-        return self
-            .request_result::<T1, String>(Method::POST, uri, None, Some(data))
-            .await;
+        self.request_result::<T1, String>(Method::POST, uri, None, Some(data))
+            .await
     }
 
     pub async fn post_json<
         T1: for<'de> serde::Deserialize<'de>,
         T2: serde::Serialize + std::clone::Clone,
     >(
-        &mut self,
+        &self,
         uri: String,
         data: T2,
     ) -> Result<T1> {
-        return self
-            .request_result::<T1, T2>(Method::POST, uri, Some(data), None)
-            .await;
+        self.request_result::<T1, T2>(Method::POST, uri, Some(data), None)
+            .await
     }
 
     pub async fn post_json_empty<T1: serde::Serialize + std::clone::Clone>(
-        &mut self,
+        &self,
         uri: String,
         data: T1,
     ) -> Result<()> {
-        match self
+        let res = self
             .request::<T1>(Method::POST, uri, Some(data), None)
-            .await
-        {
-            Ok(..) => Ok(()),
-            Err(e) => Err(e),
-        }
+            .await?;
+        Self::error_for_status(res).await?;
+        Ok(())
     }
 
     pub async fn patch_json<
         T1: for<'de> serde::Deserialize<'de>,
         T2: serde::Serialize + std::clone::Clone,
     >(
-        &mut self,
+        &self,
         uri: String,
         data: T2,
     ) -> Result<T1> {
-        return self
-            .request_result::<T1, T2>(Method::PATCH, uri, Some(data), None)
-            .await;
+        self.request_result::<T1, T2>(Method::PATCH, uri, Some(data), None)
+            .await
     }
 
-    pub async fn delete(&mut self, uri: String) -> Result<()> {
-        self.request::<String>(Method::DELETE, uri, None, None)
+    pub async fn delete(&self, uri: String) -> Result<()> {
+        let res = self
+            .request::<String>(Method::DELETE, uri, None, None)
             .await?;
+        Self::error_for_status(res).await?;
         Ok(())
     }
 
-    pub async fn get_token_user(&mut self) -> Result<User> {
-        match &self.token.user {
-            Some(v) => Ok(v.clone()),
-            None => {
-                if self.token.token_type == TokenType::UserAccessToken && self.token.user.is_none()
-                {
-                    let user = self.get_user().await?;
-                    self.token.user = Some(user.clone());
-                    return Ok(user);
+    pub fn paginate<'a, T1: for<'de> serde::Deserialize<'de> + 'a>(
+        &'a self,
+        uri: String,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<T1>> + 'a {
+        //Cursor is None once the last page was fetched, Some(None) before the first one.
+        let cursor: Option<Option<String>> = Some(None);
+        futures::stream::unfold(
+            (self, cursor, VecDeque::new(), 0),
+            move |(client, mut cursor, mut items, count)| {
+                let uri = uri.clone();
+                async move {
+                    if limit.is_some_and(|limit| count >= limit) {
+                        return None;
+                    }
+
+                    loop {
+                        if let Some(item) = items.pop_front() {
+                            return Some((Ok(item), (client, cursor, items, count + 1)));
+                        }
+
+                        let page_uri = match cursor.take()? {
+                            Some(after) => format!(
+                                "{uri}{0}after={after}",
+                                if uri.contains('?') { "&" } else { "?" }
+                            ),
+                            None => uri.clone(),
+                        };
+
+                        match client.get::<TwitchPage<T1>>(page_uri).await {
+                            Ok(page) => {
+                                if !page.data.is_empty() {
+                                    cursor = page.pagination.cursor.map(Some);
+                                }
+                                items.extend(page.data);
+                            }
+                            Err(e) => return Some((Err(e), (client, None, items, count))),
+                        }
+                    }
                 }
+            },
+        )
+    }
 
-                bail!("No User");
+    pub async fn get_token_user(&self) -> Result<User> {
+        let token_type = {
+            let token = self.token.read().await;
+            if let Some(user) = &token.user {
+                return Ok(user.clone());
             }
+            token.token_type.clone()
+        };
+
+        if token_type != TokenType::UserAccessToken {
+            return Err(Error::MissingUser);
         }
+
+        let user = self.get_user().await?;
+        self.token.write().await.user = Some(user.clone());
+        Ok(user)
     }
 
-    pub async fn get_token_user_id(&mut self) -> Result<String> {
-        match &self.token.user {
-            Some(v) => Ok(v.id.clone()),
-            None => {
-                let user = self.get_token_user().await?;
-                return Ok(user.id.clone());
-            }
-        }
+    pub async fn get_token_user_id(&self) -> Result<String> {
+        Ok(self.get_token_user().await?.id)
     }
 
-    pub async fn get_token_user_login(&mut self) -> Result<String> {
-        match &self.token.user {
-            Some(v) => Ok(v.id.clone()),
-            None => {
-                let user = self.get_token_user().await?;
-                return Ok(user.login.clone());
-            }
-        }
+    pub async fn get_token_user_login(&self) -> Result<String> {
+        Ok(self.get_token_user().await?.login)
     }
 
-    pub async fn get_users_by_ids(&mut self, user_ids: Vec<String>) -> Result<Vec<User>> {
+    pub async fn get_users_by_ids(&self, user_ids: Vec<String>) -> Result<Vec<User>> {
         Ok(self
             .get::<TwitchData<User>>(format!(
-                "https://api.twitch.tv/helix/users?id={0}",
-                user_ids.join("&id=")
+                "{helix_url}/users?id={0}",
+                user_ids.join("&id="),
+                helix_url = self.helix_url
             ))
             .await?
             .data)
     }
 
-    pub async fn get_users_by_logins(&mut self, user_logins: Vec<String>) -> Result<Vec<User>> {
+    pub async fn get_users_by_logins(&self, user_logins: Vec<String>) -> Result<Vec<User>> {
         Ok(self
             .get::<TwitchData<User>>(format!(
-                "https://api.twitch.tv/helix/users?login={0}",
-                user_logins.join("&login=")
+                "{helix_url}/users?login={0}",
+                user_logins.join("&login="),
+                helix_url = self.helix_url
             ))
             .await?
             .data)
     }
 
-    pub async fn get_user_by_id(&mut self, user_id: String) -> Result<User> {
+    pub async fn get_user_by_id(&self, user_id: String) -> Result<User> {
         match self.get_users_by_ids(vec![user_id]).await?.first() {
             Some(user) => Ok(user.clone()),
-            None => bail!("No User found"),
+            None => Err(Error::NotFound("User")),
         }
     }
 
-    pub async fn get_user_by_login(&mut self, user_login: String) -> Result<User> {
+    pub async fn get_user_by_login(&self, user_login: String) -> Result<User> {
         match self.get_users_by_logins(vec![user_login]).await?.first() {
             Some(user) => Ok(user.clone()),
-            None => bail!("No User found"),
+            None => Err(Error::NotFound("User")),
         }
     }
 
-    pub async fn get_user(&mut self) -> Result<User> {
+    pub async fn get_user(&self) -> Result<User> {
         match self
-            .get::<TwitchData<User>>("https://api.twitch.tv/helix/users".to_string())
+            .get::<TwitchData<User>>(format!("{helix_url}/users", helix_url = self.helix_url))
             .await?
             .data
             .first()
         {
             Some(user) => Ok(user.clone()),
-            None => bail!("No User found"),
+            None => Err(Error::NotFound("User")),
         }
     }
 
-    pub async fn create_custom_reward(&mut self, reward: &RewardCreate) -> Result<Reward> {
+    pub async fn create_custom_reward(&self, reward: &RewardCreate) -> Result<Reward> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
-                .post_json::<TwitchData<Reward>, _>(format!("https://api.twitch.tv/helix/channel_points/custom_rewards?broadcaster_id={broadcaster_id}"), reward)
-                .await?
-                .data
-                .first()
-            {
-                Some(reward) => Ok(reward.clone()),
-                None => bail!("No User found"),
-            }
+            .post_json::<TwitchData<Reward>, _>(
+                format!(
+                    "{helix_url}/channel_points/custom_rewards?broadcaster_id={broadcaster_id}",
+                    helix_url = self.helix_url
+                ),
+                reward,
+            )
+            .await?
+            .data
+            .first()
+        {
+            Some(reward) => Ok(reward.clone()),
+            None => Err(Error::EmptyResponse("Reward")),
+        }
     }
 
-    pub async fn update_custom_reward(
-        &mut self,
-        id: String,
-        reward: &RewardUpdate,
-    ) -> Result<Reward> {
+    pub async fn update_custom_reward(&self, id: String, reward: &RewardUpdate) -> Result<Reward> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
-                .patch_json::<TwitchData<Reward>, _>(format!("https://api.twitch.tv/helix/channel_points/custom_rewards?broadcaster_id={broadcaster_id}&id={id}"), reward)
+                .patch_json::<TwitchData<Reward>, _>(format!("{helix_url}/channel_points/custom_rewards?broadcaster_id={broadcaster_id}&id={id}", helix_url = self.helix_url), reward)
                 .await?
                 .data
                 .first()
             {
                 Some(reward) => Ok(reward.clone()),
-                None => bail!("No User found"),
+                None => Err(Error::EmptyResponse("Reward")),
             }
     }
 
-    pub async fn get_custom_rewards(&mut self, ids: Vec<String>) -> Result<Vec<Reward>> {
+    pub async fn get_custom_rewards(&self, ids: Vec<String>) -> Result<Vec<Reward>> {
         let broadcaster_id = self.get_token_user_id().await?;
         Ok(self
-                .get::<TwitchData<Reward>>(format!(
-                    "https://api.twitch.tv/helix/channel_points/custom_rewards?broadcaster_id={broadcaster_id}{0}",
-                    if ids.len() > 0 { format!("&id={0}", ids.join("&id=") ) } else { "".to_string() }
-                ))
-                .await?
-                .data)
+            .get::<TwitchData<Reward>>(format!(
+                "{helix_url}/channel_points/custom_rewards?broadcaster_id={broadcaster_id}{0}",
+                if !ids.is_empty() {
+                    format!("&id={0}", ids.join("&id="))
+                } else {
+                    "".to_string()
+                },
+                helix_url = self.helix_url
+            ))
+            .await?
+            .data)
     }
 
-    pub async fn get_custom_reward(&mut self, id: String) -> Result<Reward> {
+    pub async fn get_custom_reward(&self, id: String) -> Result<Reward> {
         match self.get_custom_rewards(vec![id]).await?.first() {
             Some(reward) => Ok(reward.clone()),
-            None => bail!("No Reward found"),
+            None => Err(Error::NotFound("Reward")),
         }
     }
 
-    pub async fn delete_custom_reward(&mut self, id: String) -> Result<()> {
+    pub async fn delete_custom_reward(&self, id: String) -> Result<()> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-                .delete(format!(
-                    "https://api.twitch.tv/helix/channel_points/custom_rewards?broadcaster_id={broadcaster_id}&id={id}"
-                ))
-                .await?)
+        self.delete(format!(
+            "{helix_url}/channel_points/custom_rewards?broadcaster_id={broadcaster_id}&id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
     pub async fn update_redemptions_status(
-        &mut self,
-        id: &String,
+        &self,
+        id: &str,
         redemptions: Vec<String>,
         status: &RedemptionStatus,
     ) -> Result<Vec<RedemptionStatus>> {
         let broadcaster_id = self.get_token_user_id().await?;
         Ok(self
                 .patch_json::<TwitchData<RedemptionStatus>, _>(format!(
-                    "https://api.twitch.tv/helix/channel_points/custom_rewards/redemptions?broadcaster_id={broadcaster_id}&reward_id={id}{0}",
-                    format!("&id={0}", redemptions.join("&id=") )
+                    "{helix_url}/channel_points/custom_rewards/redemptions?broadcaster_id={broadcaster_id}&reward_id={id}&id={0}",
+                    redemptions.join("&id="),
+                    helix_url = self.helix_url
                 ), status)
                 .await?
                 .data)
     }
 
     pub async fn update_redemption_status(
-        &mut self,
-        id: &String,
-        redemption: &String,
+        &self,
+        id: &str,
+        redemption: &str,
         status: &RedemptionStatus,
     ) -> Result<RedemptionStatus> {
         match self
-            .update_redemptions_status(id, vec![redemption.clone()], status)
+            .update_redemptions_status(id, vec![redemption.to_string()], status)
             .await?
             .first()
         {
             Some(status) => Ok(status.clone()),
-            None => bail!("No Redemption found"),
+            None => Err(Error::EmptyResponse("Redemption")),
         }
     }
 
     pub async fn create_eventsub_subscription(
-        &mut self,
+        &self,
         eventsub: &EventSubCreate,
     ) -> Result<EventSub> {
+        //Types missing from the catalogue are sent as is.
+        if let Ok(subscription_type) = eventsub.r#type.parse::<crate::eventsub::SubscriptionType>()
+        {
+            subscription_type.check_condition(&eventsub.condition)?;
+        }
+
         let eventsubs = self
             .post_json::<TwitchData<EventSub>, _>(
-                format!("https://api.twitch.tv/helix/eventsub/subscriptions"),
+                format!(
+                    "{helix_url}/eventsub/subscriptions",
+                    helix_url = self.helix_url
+                ),
                 eventsub,
             )
             .await?;
 
-        if let Some(e) = eventsubs.error {
-            bail!(e);
-        }
-
         match eventsubs.data.first() {
             Some(eventsub) => Ok(eventsub.clone()),
-            None => bail!("No EventSub found"),
+            None => Err(Error::EmptyResponse("EventSub")),
         }
     }
 
-    pub async fn delete_eventsub_subscription(&mut self, id: String) -> Result<()> {
+    pub async fn delete_eventsub_subscription(&self, id: String) -> Result<()> {
+        self.delete(format!(
+            "{helix_url}/eventsub/subscriptions?id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
+    }
+
+    fn eventsub_subscriptions_uri(&self, filter: Option<EventSubFilter>) -> String {
+        format!(
+            "{helix_url}/eventsub/subscriptions{0}",
+            match filter {
+                Some(EventSubFilter::Status(value)) => format!("?status={value}"),
+                Some(EventSubFilter::Type(value)) => format!("?type={value}"),
+                Some(EventSubFilter::UserId(value)) => format!("?user_id={value}"),
+                None => "".to_string(),
+            },
+            helix_url = self.helix_url
+        )
+    }
+
+    pub async fn get_eventsub_subscriptions(
+        &self,
+        filter: Option<EventSubFilter>,
+        after: Option<String>,
+    ) -> Result<EventSubPage> {
+        let uri = self.eventsub_subscriptions_uri(filter);
+        self.get::<EventSubPage>(match after {
+            Some(after) => format!(
+                "{uri}{0}after={after}",
+                if uri.contains('?') { "&" } else { "?" }
+            ),
+            None => uri,
+        })
+        .await
+    }
+
+    pub fn paginate_eventsub_subscriptions(
+        &self,
+        filter: Option<EventSubFilter>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<EventSub>> + '_ {
+        self.paginate(self.eventsub_subscriptions_uri(filter), limit)
+    }
+
+    //Deletes subscriptions that failed, pending ones are still being verified and are left alone.
+    //With active_sessions, enabled websocket subscriptions on any other session are deleted too.
+    //That includes live sessions of other processes using the same client id, so pass every
+    //session still in use, Some(&[]) deletes all websocket subscriptions.
+    pub async fn cleanup_eventsub_subscriptions(
+        &self,
+        active_sessions: Option<&[String]>,
+    ) -> Result<usize> {
+        let subscriptions: Vec<EventSub> = self
+            .paginate_eventsub_subscriptions(None, None)
+            .try_collect()
+            .await?;
+
+        let mut deleted = 0;
+        for subscription in subscriptions {
+            if subscription.status.ends_with("_pending") {
+                continue;
+            }
+
+            let stale = subscription.status != "enabled"
+                || active_sessions.is_some_and(|active_sessions| {
+                    subscription.transport.method == "websocket"
+                        && !subscription
+                            .transport
+                            .session_id
+                            .as_ref()
+                            .is_some_and(|id| active_sessions.contains(id))
+                });
+            if !stale {
+                continue;
+            }
+
+            //A 404 means something else already deleted it.
+            match self.delete_eventsub_subscription(subscription.id).await {
+                Ok(()) => deleted += 1,
+                Err(Error::Twitch(TwitchError { status: 404, .. })) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(deleted)
+    }
+
+    pub async fn get_conduits(&self) -> Result<Vec<Conduit>> {
         Ok(self
-            .delete(format!(
-                "https://api.twitch.tv/helix/eventsub/subscriptions?id={id}"
+            .get::<TwitchData<Conduit>>(format!(
+                "{helix_url}/eventsub/conduits",
+                helix_url = self.helix_url
             ))
-            .await?)
+            .await?
+            .data)
     }
 
-    pub async fn add_channel_moderator(&mut self, id: String) -> Result<()> {
+    pub async fn create_conduit(&self, shard_count: i64) -> Result<Conduit> {
+        match self
+            .post_json::<TwitchData<Conduit>, _>(
+                format!("{helix_url}/eventsub/conduits", helix_url = self.helix_url),
+                ConduitCreate { shard_count },
+            )
+            .await?
+            .data
+            .first()
+        {
+            Some(conduit) => Ok(conduit.clone()),
+            None => Err(Error::EmptyResponse("Conduit")),
+        }
+    }
+
+    pub async fn update_conduit(&self, id: String, shard_count: i64) -> Result<Conduit> {
+        match self
+            .patch_json::<TwitchData<Conduit>, _>(
+                format!("{helix_url}/eventsub/conduits", helix_url = self.helix_url),
+                ConduitUpdate { id, shard_count },
+            )
+            .await?
+            .data
+            .first()
+        {
+            Some(conduit) => Ok(conduit.clone()),
+            None => Err(Error::EmptyResponse("Conduit")),
+        }
+    }
+
+    pub async fn delete_conduit(&self, id: String) -> Result<()> {
+        self.delete(format!(
+            "{helix_url}/eventsub/conduits?id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
+    }
+
+    fn conduit_shards_uri(&self, conduit_id: &str, status: Option<String>) -> String {
+        format!(
+            "{helix_url}/eventsub/conduits/shards?conduit_id={conduit_id}{0}",
+            if let Some(status) = status {
+                format!("&status={status}")
+            } else {
+                "".to_string()
+            },
+            helix_url = self.helix_url
+        )
+    }
+
+    pub async fn get_conduit_shards(
+        &self,
+        conduit_id: String,
+        status: Option<String>,
+        after: Option<String>,
+    ) -> Result<TwitchPage<ConduitShard>> {
+        self.get::<TwitchPage<ConduitShard>>(format!(
+            "{0}{1}",
+            self.conduit_shards_uri(&conduit_id, status),
+            if let Some(after) = after {
+                format!("&after={after}")
+            } else {
+                "".to_string()
+            },
+        ))
+        .await
+    }
+
+    pub fn paginate_conduit_shards(
+        &self,
+        conduit_id: String,
+        status: Option<String>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<ConduitShard>> + '_ {
+        self.paginate(self.conduit_shards_uri(&conduit_id, status), limit)
+    }
+
+    pub async fn update_conduit_shards(
+        &self,
+        conduit_id: String,
+        shards: Vec<ConduitShardUpdate>,
+    ) -> Result<ConduitShardsUpdated> {
+        self.patch_json::<ConduitShardsUpdated, _>(
+            format!(
+                "{helix_url}/eventsub/conduits/shards",
+                helix_url = self.helix_url
+            ),
+            ConduitShardsUpdate { conduit_id, shards },
+        )
+        .await
+    }
+
+    pub async fn add_channel_moderator(&self, id: String) -> Result<()> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-                .post_empty(format!(
-                    "https://api.twitch.tv/helix/moderation/moderators?broadcaster_id={broadcaster_id}&user_id={id}"
-                ))
-                .await?)
+        self.post_empty(format!(
+            "{helix_url}/moderation/moderators?broadcaster_id={broadcaster_id}&user_id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
-    pub async fn remove_channel_moderator(&mut self, id: String) -> Result<()> {
+    pub async fn remove_channel_moderator(&self, id: String) -> Result<()> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-                .delete(format!(
-                    "https://api.twitch.tv/helix/moderation/moderators?broadcaster_id={broadcaster_id}&user_id={id}"
-                ))
-                .await?)
+        self.delete(format!(
+            "{helix_url}/moderation/moderators?broadcaster_id={broadcaster_id}&user_id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
-    pub async fn ban_user(
-        &mut self,
-        broadcaster_id: String,
-        banuser: &BanUser,
-    ) -> Result<BannedUser> {
+    pub async fn ban_user(&self, broadcaster_id: String, banuser: &BanUser) -> Result<BannedUser> {
         let moderator_id = self.get_token_user_id().await?;
         match self
                 .post_json::<TwitchData<BannedUser>, _>(
-                    format!("https://api.twitch.tv/helix/moderation/bans?moderator_id={moderator_id}&broadcaster_id={broadcaster_id}"),
+                    format!("{helix_url}/moderation/bans?moderator_id={moderator_id}&broadcaster_id={broadcaster_id}", helix_url = self.helix_url),
                     BanUserObj {
                         data: banuser.clone()
                     },
@@ -732,96 +1288,128 @@ impl<T: TokenStorage> Client<T> {
                 .first()
             {
                 Some(banneduser) => Ok(banneduser.clone()),
-                None => bail!("Ban User failed"),
+                None => Err(Error::EmptyResponse("Banned User")),
             }
     }
 
-    pub async fn unban_user(&mut self, broadcaster_id: String, user_id: String) -> Result<()> {
+    pub async fn unban_user(&self, broadcaster_id: String, user_id: String) -> Result<()> {
         let moderator_id = self.get_token_user_id().await?;
-        Ok(self
-                .delete(format!(
-                    "https://api.twitch.tv/helix/moderation/bans?moderator_id={moderator_id}&broadcaster_id={broadcaster_id}&user_id={user_id}"
-                ))
-                .await?)
+        self.delete(format!(
+            "{helix_url}/moderation/bans?moderator_id={moderator_id}&broadcaster_id={broadcaster_id}&user_id={user_id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
     pub async fn shoutout(
-        &mut self,
+        &self,
         from_broadcaster_id: String,
         to_broadcaster_id: String,
     ) -> Result<()> {
         let moderator_id = self.get_token_user_id().await?;
-        Ok(self
-                .post_empty(format!(
-                    "https://api.twitch.tv/helix/chat/shoutouts?from_broadcaster_id={from_broadcaster_id}&to_broadcaster_id={to_broadcaster_id}&moderator_id={moderator_id}"
-                ))
-                .await?)
+        self.post_empty(format!(
+            "{helix_url}/chat/shoutouts?from_broadcaster_id={from_broadcaster_id}&to_broadcaster_id={to_broadcaster_id}&moderator_id={moderator_id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
+    //Helix returns no cursor here, so there is no page or paginate form.
     pub async fn get_channel_information(
-        &mut self,
+        &self,
         broadcaster_ids: Vec<String>,
     ) -> Result<Vec<ChannelInformation>> {
         Ok(self
             .get::<TwitchData<ChannelInformation>>(format!(
-                "https://api.twitch.tv/helix/channels?{0}",
-                if broadcaster_ids.len() > 0 {
+                "{helix_url}/channels?{0}",
+                if !broadcaster_ids.is_empty() {
                     format!(
                         "broadcaster_id={0}",
                         broadcaster_ids.join("&broadcaster_id=")
                     )
                 } else {
                     "".to_string()
-                }
+                },
+                helix_url = self.helix_url
             ))
             .await?
             .data)
     }
 
-    pub async fn whisper(&mut self, to_user_id: String, message: String) -> Result<()> {
+    pub async fn whisper(&self, to_user_id: String, message: String) -> Result<()> {
         let from_user_id = self.get_token_user_id().await?;
-        Ok(self
-                .post_json_empty(
-                    format!("https://api.twitch.tv/helix/whispers?from_user_id={from_user_id}&to_user_id={to_user_id}"),
-                    Whisper {
-                        message: message
-                    },
-                )
-                .await?)
+        self.post_json_empty(
+            format!(
+                "{helix_url}/whispers?from_user_id={from_user_id}&to_user_id={to_user_id}",
+                helix_url = self.helix_url
+            ),
+            Whisper { message },
+        )
+        .await
+    }
+
+    fn predictions_uri(
+        &self,
+        broadcaster_id: &str,
+        id: Option<String>,
+        first: Option<String>,
+    ) -> String {
+        format!(
+            "{helix_url}/predictions?broadcaster_id={broadcaster_id}{0}{1}",
+            if let Some(id) = id {
+                format!("&id={id}")
+            } else {
+                "".to_string()
+            },
+            if let Some(first) = first {
+                format!("&first={first}")
+            } else {
+                "".to_string()
+            },
+            helix_url = self.helix_url
+        )
     }
 
     pub async fn get_predictions(
-        &mut self,
+        &self,
         id: Option<String>,
         first: Option<String>,
         after: Option<String>,
     ) -> Result<Vec<Prediction>> {
+        Ok(self.get_predictions_page(id, first, after).await?.data)
+    }
+
+    pub async fn get_predictions_page(
+        &self,
+        id: Option<String>,
+        first: Option<String>,
+        after: Option<String>,
+    ) -> Result<TwitchPage<Prediction>> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-            .get::<TwitchData<Prediction>>(format!(
-                "https://api.twitch.tv/helix/predictions?broadcaster_id={broadcaster_id}{0}{1}{2}",
-                if let Some(id) = id {
-                    format!("&id={id}")
-                } else {
-                    "".to_string()
-                },
-                if let Some(first) = first {
-                    format!("&first={first}")
-                } else {
-                    "".to_string()
-                },
-                if let Some(after) = after {
-                    format!("&after={after}")
-                } else {
-                    "".to_string()
-                },
-            ))
-            .await?
-            .data)
+        self.get::<TwitchPage<Prediction>>(format!(
+            "{0}{1}",
+            self.predictions_uri(&broadcaster_id, id, first),
+            if let Some(after) = after {
+                format!("&after={after}")
+            } else {
+                "".to_string()
+            },
+        ))
+        .await
+    }
+
+    pub async fn paginate_predictions(
+        &self,
+        id: Option<String>,
+        first: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<impl futures::Stream<Item = Result<Prediction>> + '_> {
+        let broadcaster_id = self.get_token_user_id().await?;
+        Ok(self.paginate(self.predictions_uri(&broadcaster_id, id, first), limit))
     }
 
     pub async fn create_prediction(
-        &mut self,
+        &self,
         title: String,
         outcomes: Vec<String>,
         prediction_window: i64,
@@ -829,15 +1417,15 @@ impl<T: TokenStorage> Client<T> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
             .post_json::<TwitchData<Prediction>, _>(
-                "https://api.twitch.tv/helix/predictions".to_string(),
+                format!("{helix_url}/predictions", helix_url = self.helix_url),
                 PredictionCreate {
-                    broadcaster_id: broadcaster_id,
-                    title: title,
+                    broadcaster_id,
+                    title,
                     outcomes: outcomes
                         .into_iter()
                         .map(|o| PredictionOutcomeCreate { title: o })
                         .collect(),
-                    prediction_window: prediction_window,
+                    prediction_window,
                 },
             )
             .await?
@@ -845,12 +1433,12 @@ impl<T: TokenStorage> Client<T> {
             .first()
         {
             Some(prediction) => Ok(prediction.clone()),
-            None => bail!("Create Prediction failed"),
+            None => Err(Error::EmptyResponse("Prediction")),
         }
     }
 
     pub async fn end_prediction(
-        &mut self,
+        &self,
         id: String,
         status: String,
         winning_outcome_id: Option<String>,
@@ -858,12 +1446,12 @@ impl<T: TokenStorage> Client<T> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
             .patch_json::<TwitchData<Prediction>, _>(
-                "https://api.twitch.tv/helix/predictions".to_string(),
+                format!("{helix_url}/predictions", helix_url = self.helix_url),
                 PredictionEnd {
-                    broadcaster_id: broadcaster_id,
-                    id: id,
-                    status: status,
-                    winning_outcome_id: winning_outcome_id,
+                    broadcaster_id,
+                    id,
+                    status,
+                    winning_outcome_id,
                 },
             )
             .await?
@@ -871,36 +1459,35 @@ impl<T: TokenStorage> Client<T> {
             .first()
         {
             Some(prediction) => Ok(prediction.clone()),
-            None => bail!("End Prediction failed"),
+            None => Err(Error::EmptyResponse("Prediction")),
         }
     }
 
     pub async fn send_chat_announcement(
-        &mut self,
+        &self,
         broadcaster_id: String,
         message: String,
         color: Option<String>,
     ) -> Result<()> {
         let moderator_id = self.get_token_user_id().await?;
-        Ok(self
-                .post_json_empty(
-                    format!("https://api.twitch.tv/helix/chat/announcements?broadcaster_id={broadcaster_id}&moderator_id={moderator_id}"),
-                    Announcement {
-                        message: message,
-                        color: color,
-                    }
-                )
-                .await?)
+        self.post_json_empty(
+            format!("{helix_url}/chat/announcements?broadcaster_id={broadcaster_id}&moderator_id={moderator_id}", helix_url = self.helix_url),
+            Announcement { message, color },
+        )
+        .await
     }
 
-    pub async fn start_commercial(&mut self, length: i64) -> Result<Commercial> {
+    pub async fn start_commercial(&self, length: i64) -> Result<Commercial> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
             .post_json::<TwitchData<Commercial>, _>(
-                "https://api.twitch.tv/helix/channels/commercial".to_string(),
+                format!(
+                    "{helix_url}/channels/commercial",
+                    helix_url = self.helix_url
+                ),
                 CommercialStart {
-                    broadcaster_id: broadcaster_id,
-                    length: length,
+                    broadcaster_id,
+                    length,
                 },
             )
             .await?
@@ -908,12 +1495,58 @@ impl<T: TokenStorage> Client<T> {
             .first()
         {
             Some(commercial) => Ok(commercial.clone()),
-            None => bail!("Start Commercial failed"),
+            None => Err(Error::EmptyResponse("Commercial")),
         }
     }
 
+    fn streams_uri(
+        &self,
+        user_ids: Option<Vec<String>>,
+        user_logins: Option<Vec<String>>,
+        game_ids: Option<Vec<String>>,
+        r#type: Option<String>,
+        languages: Option<Vec<String>>,
+        first: Option<i64>,
+    ) -> String {
+        format!(
+            "{helix_url}/streams?{0}{1}{2}{3}{4}{5}",
+            if let Some(user_ids) = user_ids {
+                format!("&user_id={}", user_ids.join("&user_id="))
+            } else {
+                "".to_string()
+            },
+            if let Some(user_logins) = user_logins {
+                format!("&user_login={}", user_logins.join("&user_login="))
+            } else {
+                "".to_string()
+            },
+            if let Some(game_ids) = game_ids {
+                format!("&game_id={}", game_ids.join("&game_id="))
+            } else {
+                "".to_string()
+            },
+            if let Some(type_) = r#type {
+                format!("&type={type_}")
+            } else {
+                "".to_string()
+            },
+            if let Some(languages) = languages {
+                format!("&language={}", languages.join("&language="))
+            } else {
+                "".to_string()
+            },
+            if let Some(first) = first {
+                format!("&first={first}")
+            } else {
+                "".to_string()
+            },
+            helix_url = self.helix_url
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_streams(
-        &mut self,
+        &self,
         user_ids: Option<Vec<String>>,
         user_logins: Option<Vec<String>>,
         game_ids: Option<Vec<String>>,
@@ -924,54 +1557,67 @@ impl<T: TokenStorage> Client<T> {
         after: Option<String>,
     ) -> Result<Vec<Stream>> {
         Ok(self
-            .get::<TwitchData<Stream>>(format!(
-                "https://api.twitch.tv/helix/streams?{0}{1}{2}{3}{4}{5}{6}{7}",
-                if let Some(user_ids) = user_ids {
-                    format!("&user_id={}", user_ids.join("&user_id="))
-                } else {
-                    "".to_string()
-                },
-                if let Some(user_logins) = user_logins {
-                    format!("&user_login={}", user_logins.join("&user_login="))
-                } else {
-                    "".to_string()
-                },
-                if let Some(game_ids) = game_ids {
-                    format!("&game_id={}", game_ids.join("&game_id="))
-                } else {
-                    "".to_string()
-                },
-                if let Some(type_) = r#type {
-                    format!("&type={type_}")
-                } else {
-                    "".to_string()
-                },
-                if let Some(languages) = languages {
-                    format!("&language={}", languages.join("&language="))
-                } else {
-                    "".to_string()
-                },
-                if let Some(first) = first {
-                    format!("&first={first}")
-                } else {
-                    "".to_string()
-                },
-                if let Some(before) = before {
-                    format!("&before={before}")
-                } else {
-                    "".to_string()
-                },
-                if let Some(after) = after {
-                    format!("&after={after}")
-                } else {
-                    "".to_string()
-                },
-            ))
+            .get_streams_page(
+                user_ids,
+                user_logins,
+                game_ids,
+                r#type,
+                languages,
+                first,
+                before,
+                after,
+            )
             .await?
             .data)
     }
 
-    pub async fn get_stream(&mut self) -> Result<Stream> {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_streams_page(
+        &self,
+        user_ids: Option<Vec<String>>,
+        user_logins: Option<Vec<String>>,
+        game_ids: Option<Vec<String>>,
+        r#type: Option<String>,
+        languages: Option<Vec<String>>,
+        first: Option<i64>,
+        before: Option<String>,
+        after: Option<String>,
+    ) -> Result<TwitchPage<Stream>> {
+        self.get::<TwitchPage<Stream>>(format!(
+            "{0}{1}{2}",
+            self.streams_uri(user_ids, user_logins, game_ids, r#type, languages, first),
+            if let Some(before) = before {
+                format!("&before={before}")
+            } else {
+                "".to_string()
+            },
+            if let Some(after) = after {
+                format!("&after={after}")
+            } else {
+                "".to_string()
+            },
+        ))
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn paginate_streams(
+        &self,
+        user_ids: Option<Vec<String>>,
+        user_logins: Option<Vec<String>>,
+        game_ids: Option<Vec<String>>,
+        r#type: Option<String>,
+        languages: Option<Vec<String>>,
+        first: Option<i64>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Stream>> + '_ {
+        self.paginate(
+            self.streams_uri(user_ids, user_logins, game_ids, r#type, languages, first),
+            limit,
+        )
+    }
+
+    pub async fn get_stream(&self) -> Result<Stream> {
         let broadcaster_id = self.get_token_user_id().await?;
         match self
             .get_streams(
@@ -988,40 +1634,137 @@ impl<T: TokenStorage> Client<T> {
             .first()
         {
             Some(stream) => Ok(stream.clone()),
-            None => bail!("No stream found"),
+            None => Err(Error::NotFound("Stream")),
         }
     }
 
-    pub async fn add_channel_vip(&mut self, id: String) -> Result<()> {
+    pub async fn add_channel_vip(&self, id: String) -> Result<()> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-                .post_empty(format!(
-                    "https://api.twitch.tv/helix/channels/vips?broadcaster_id={broadcaster_id}&user_id={id}"
-                ))
-                .await?)
+        self.post_empty(format!(
+            "{helix_url}/channels/vips?broadcaster_id={broadcaster_id}&user_id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
     }
 
-    pub async fn remove_channel_vip(&mut self, id: String) -> Result<()> {
+    pub async fn remove_channel_vip(&self, id: String) -> Result<()> {
         let broadcaster_id = self.get_token_user_id().await?;
-        Ok(self
-                .delete(format!(
-                    "https://api.twitch.tv/helix/channels/vips?broadcaster_id={broadcaster_id}&user_id={id}"
-                ))
-                .await?)
+        self.delete(format!(
+            "{helix_url}/channels/vips?broadcaster_id={broadcaster_id}&user_id={id}",
+            helix_url = self.helix_url
+        ))
+        .await
+    }
+
+    fn channel_followers_uri(
+        &self,
+        broadcaster_id: String,
+        user_id: Option<String>,
+        first: Option<i64>,
+    ) -> String {
+        format!(
+            "{helix_url}/channels/followers?broadcaster_id={broadcaster_id}{0}{1}",
+            if let Some(user_id) = user_id {
+                format!("&user_id={user_id}")
+            } else {
+                "".to_string()
+            },
+            if let Some(first) = first {
+                format!("&first={first}")
+            } else {
+                "".to_string()
+            },
+            helix_url = self.helix_url
+        )
+    }
+
+    pub async fn get_channel_followers(
+        &self,
+        broadcaster_id: String,
+        user_id: Option<String>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<ChannelFollowersData> {
+        self.get::<ChannelFollowersData>(format!(
+            "{0}{1}",
+            self.channel_followers_uri(broadcaster_id, user_id, first),
+            if let Some(after) = after {
+                format!("&after={after}")
+            } else {
+                "".to_string()
+            },
+        ))
+        .await
     }
 
-    pub async fn get_channel_followers_total(&mut self, broadcaster_id: String) -> Result<i64> {
+    pub fn paginate_channel_followers(
+        &self,
+        broadcaster_id: String,
+        user_id: Option<String>,
+        first: Option<i64>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<ChannelFollowers>> + '_ {
+        self.paginate(
+            self.channel_followers_uri(broadcaster_id, user_id, first),
+            limit,
+        )
+    }
+
+    pub async fn get_channel_followers_total(&self, broadcaster_id: String) -> Result<i64> {
         Ok(self
-            .get::<ChannelFollowersData>(format!(
-                "https://api.twitch.tv/helix/channels/followers?broadcaster_id={0}",
-                broadcaster_id
-            ))
+            .get_channel_followers(broadcaster_id, None, None, None)
             .await?
             .total)
     }
 
+    fn videos_uri(
+        &self,
+        id: VideoId,
+        language: Option<String>,
+        period: Option<String>,
+        sort: Option<String>,
+        r#type: Option<String>,
+        first: Option<String>,
+    ) -> String {
+        format!(
+            "{helix_url}/videos?{}{}{}{}{}{}",
+            match id {
+                VideoId::Id(value) => format!("id={}", value),
+                VideoId::UserId(value) => format!("user_id={}", value),
+                VideoId::GameId(value) => format!("game_id={}", value),
+            },
+            if let Some(value) = language {
+                format!("&language={}", value)
+            } else {
+                "".to_string()
+            },
+            if let Some(value) = period {
+                format!("&period={}", value)
+            } else {
+                "".to_string()
+            },
+            if let Some(value) = sort {
+                format!("&sort={}", value)
+            } else {
+                "".to_string()
+            },
+            if let Some(value) = r#type {
+                format!("&type={}", value)
+            } else {
+                "".to_string()
+            },
+            if let Some(value) = first {
+                format!("&first={}", value)
+            } else {
+                "".to_string()
+            },
+            helix_url = self.helix_url
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_videos(
-        &mut self,
+        &self,
         id: VideoId,
         language: Option<String>,
         period: Option<String>,
@@ -1032,50 +1775,54 @@ impl<T: TokenStorage> Client<T> {
         before: Option<String>,
     ) -> Result<Vec<Video>> {
         Ok(self
-            .get::<TwitchData<Video>>(format!(
-                "https://api.twitch.tv/helix/videos?{}{}{}{}{}{}{}{}",
-                match id {
-                    VideoId::Id(value) => format!("id={}", value),
-                    VideoId::UserId(value) => format!("user_id={}", value),
-                    VideoId::GameId(value) => format!("game_id={}", value),
-                },
-                if let Some(value) = language {
-                    format!("&language={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = period {
-                    format!("&period={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = sort {
-                    format!("&sort={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = r#type {
-                    format!("&type={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = first {
-                    format!("&first={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = after {
-                    format!("&after={}", value)
-                } else {
-                    "".to_string()
-                },
-                if let Some(value) = before {
-                    format!("&before={}", value)
-                } else {
-                    "".to_string()
-                }
-            ))
+            .get_videos_page(id, language, period, sort, r#type, first, after, before)
             .await?
             .data)
     }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_videos_page(
+        &self,
+        id: VideoId,
+        language: Option<String>,
+        period: Option<String>,
+        sort: Option<String>,
+        r#type: Option<String>,
+        first: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<TwitchPage<Video>> {
+        self.get::<TwitchPage<Video>>(format!(
+            "{}{}{}",
+            self.videos_uri(id, language, period, sort, r#type, first),
+            if let Some(value) = after {
+                format!("&after={}", value)
+            } else {
+                "".to_string()
+            },
+            if let Some(value) = before {
+                format!("&before={}", value)
+            } else {
+                "".to_string()
+            }
+        ))
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn paginate_videos(
+        &self,
+        id: VideoId,
+        language: Option<String>,
+        period: Option<String>,
+        sort: Option<String>,
+        r#type: Option<String>,
+        first: Option<String>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Video>> + '_ {
+        self.paginate(
+            self.videos_uri(id, language, period, sort, r#type, first),
+            limit,
+        )
+    }
 }
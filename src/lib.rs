@@ -3,8 +3,15 @@ pub mod auth;
 pub mod bevy;
 #[cfg(feature = "chat")]
 pub mod chat;
+pub mod error;
 pub mod eventsub;
 pub mod helix;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod ratelimit;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 pub use anyhow;
 pub use async_trait;
+pub use error::{Error, Result};
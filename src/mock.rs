@@ -0,0 +1,1387 @@
+use crate::auth::{Token, VoidStorage};
+use crate::eventsub::SubscriptionType;
+use crate::helix::{
+    ChannelFollowers, Client, ClientBuilder, Conduit, ConduitCreate, ConduitShard,
+    ConduitShardError, ConduitShardsUpdate, ConduitUpdate, EventSub, EventSubCreate, Reward,
+    RewardCreate, RewardGlobalCooldown, RewardImage, RewardMaxPerStream, RewardMaxPerUserPerStream,
+    RewardUpdate, Stream, User,
+};
+
+use axum::body::Bytes;
+use axum::extract::{FromRef, State};
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type SharedFixtures = Arc<Mutex<Fixtures>>;
+type Sessions = Arc<Mutex<HashMap<String, UnboundedSender<Message>>>>;
+type MockResult = std::result::Result<Response, Response>;
+
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    pub client_id: String,
+    pub client_secret: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub user_id: String,
+    pub scopes: Vec<String>,
+    pub users: Vec<User>,
+    pub streams: Vec<Stream>,
+    pub rewards: Vec<Reward>,
+    //Followers by broadcaster id.
+    pub followers: HashMap<String, Vec<ChannelFollowers>>,
+    pub subscriptions: Vec<EventSub>,
+    pub conduits: Vec<Conduit>,
+    pub conduit_shards: HashMap<String, Vec<ConduitShard>>,
+    pub keepalive_timeout_seconds: i64,
+    //While set, EventSub connections are dropped before the handshake.
+    pub eventsub_unavailable: bool,
+    //Whether the old connection of a reconnecting session is closed once the new one is welcomed.
+    pub close_reconnected_sessions: bool,
+    pub rate_limit: u32,
+    pub rate_limit_remaining: u32,
+    //The next this many Helix requests are answered with 429.
+    pub rate_limited_requests: usize,
+    pub token_requests: usize,
+    pub token_user_agent: Option<String>,
+    pub next_id: u64,
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        let user = Fixtures::user("1000", "mock_user");
+        let other = Fixtures::user("1001", "other_user");
+
+        Fixtures {
+            client_id: "mock-client-id".to_string(),
+            client_secret: "mock-client-secret".to_string(),
+            access_token: "mock-access-token".to_string(),
+            refresh_token: "mock-refresh-token".to_string(),
+            user_id: user.id.clone(),
+            scopes: Vec::new(),
+            streams: vec![Fixtures::stream(&user)],
+            rewards: vec![Fixtures::reward(&user, "reward-1", "Hydrate", 100)],
+            followers: HashMap::from([(
+                user.id.clone(),
+                vec![Fixtures::follower(&other, "2021-01-01T00:00:00Z")],
+            )]),
+            users: vec![user, other],
+            subscriptions: Vec::new(),
+            conduits: Vec::new(),
+            conduit_shards: HashMap::new(),
+            keepalive_timeout_seconds: 10,
+            eventsub_unavailable: false,
+            close_reconnected_sessions: true,
+            rate_limit: 800,
+            rate_limit_remaining: 800,
+            rate_limited_requests: 0,
+            token_requests: 0,
+            token_user_agent: None,
+            next_id: 1,
+        }
+    }
+}
+
+impl Fixtures {
+    pub fn user(id: &str, login: &str) -> User {
+        User {
+            id: id.to_string(),
+            login: login.to_string(),
+            display_name: login.to_string(),
+            r#type: "".to_string(),
+            broadcaster_type: "".to_string(),
+            description: "".to_string(),
+            profile_image_url: "".to_string(),
+            offline_image_url: "".to_string(),
+            view_count: 0,
+            email: None,
+            created_at: "2020-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    pub fn stream(user: &User) -> Stream {
+        Stream {
+            id: format!("stream-{0}", user.id),
+            user_id: user.id.clone(),
+            user_login: user.login.clone(),
+            user_name: user.display_name.clone(),
+            game_id: "509658".to_string(),
+            game_name: "Just Chatting".to_string(),
+            r#type: "live".to_string(),
+            title: format!("{0} is live", user.display_name),
+            tags: Vec::new(),
+            viewer_count: 1,
+            started_at: "2020-01-01T00:00:00Z".to_string(),
+            language: "en".to_string(),
+            thumbnail_url: "".to_string(),
+            is_mature: false,
+        }
+    }
+
+    pub fn reward(broadcaster: &User, id: &str, title: &str, cost: i64) -> Reward {
+        let image = RewardImage {
+            url_1x: "".to_string(),
+            url_2x: "".to_string(),
+            url_4x: "".to_string(),
+        };
+
+        Reward {
+            broadcaster_id: broadcaster.id.clone(),
+            broadcaster_login: broadcaster.login.clone(),
+            broadcaster_name: broadcaster.display_name.clone(),
+            id: id.to_string(),
+            title: title.to_string(),
+            prompt: "".to_string(),
+            cost,
+            image: None,
+            default_image: image,
+            background_color: "#9147FF".to_string(),
+            is_enabled: true,
+            is_user_input_required: false,
+            max_per_stream_setting: RewardMaxPerStream {
+                is_enabled: false,
+                max_per_stream: 0,
+            },
+            max_per_user_per_stream_setting: RewardMaxPerUserPerStream {
+                is_enabled: false,
+                max_per_user_per_stream: 0,
+            },
+            global_cooldown_setting: RewardGlobalCooldown {
+                is_enabled: false,
+                global_cooldown_seconds: 0,
+            },
+            is_paused: false,
+            is_in_stock: true,
+            should_redemptions_skip_request_queue: false,
+            redemptions_redeemed_current_stream: None,
+            cooldown_expires_at: None,
+        }
+    }
+
+    pub fn follower(user: &User, followed_at: &str) -> ChannelFollowers {
+        ChannelFollowers {
+            followed_at: followed_at.to_string(),
+            user_id: user.id.clone(),
+            user_login: user.login.clone(),
+            user_name: user.display_name.clone(),
+        }
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        let id = format!("{prefix}-{0}", self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn token_user(&self) -> Option<&User> {
+        self.users.iter().find(|u| u.id == self.user_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockState {
+    fixtures: SharedFixtures,
+    sessions: Sessions,
+}
+
+impl FromRef<MockState> for SharedFixtures {
+    fn from_ref(state: &MockState) -> Self {
+        state.fixtures.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    eventsub_addr: SocketAddr,
+    state: MockState,
+    tasks: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub async fn start() -> std::io::Result<MockServer> {
+        Self::with_fixtures(Fixtures::default()).await
+    }
+
+    pub async fn with_fixtures(fixtures: Fixtures) -> std::io::Result<MockServer> {
+        let state = MockState {
+            fixtures: Arc::new(Mutex::new(fixtures)),
+            sessions: Default::default(),
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let router = router(state.clone());
+        let helix = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                log::error!("Mock server failed: {e}");
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let eventsub_addr = listener.local_addr()?;
+        let eventsub = tokio::spawn(serve_eventsub(listener, state.clone()));
+
+        Ok(MockServer {
+            addr,
+            eventsub_addr,
+            state,
+            tasks: vec![helix, eventsub],
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{0}", self.addr)
+    }
+
+    pub fn helix_url(&self) -> String {
+        format!("{0}/helix", self.url())
+    }
+
+    pub fn oauth_url(&self) -> String {
+        format!("{0}/oauth2", self.url())
+    }
+
+    pub fn eventsub_url(&self) -> String {
+        format!("ws://{0}/ws", self.eventsub_addr)
+    }
+
+    pub fn fixtures(&self) -> MutexGuard<'_, Fixtures> {
+        self.state.fixtures.lock().unwrap()
+    }
+
+    pub fn client_builder(&self) -> ClientBuilder<VoidStorage> {
+        let fixtures = self.fixtures();
+        ClientBuilder::new(fixtures.client_id.clone(), fixtures.client_secret.clone())
+            .token(Token {
+                access_token: fixtures.access_token.clone(),
+                refresh_token: fixtures.refresh_token.clone(),
+                expires_in: 3600,
+                ..Default::default()
+            })
+            .helix_url(self.helix_url())
+            .oauth_url(self.oauth_url())
+            .eventsub_url(self.eventsub_url())
+    }
+
+    pub fn client(&self) -> Client<VoidStorage> {
+        self.client_builder().build()
+    }
+
+    pub fn sessions(&self) -> Vec<String> {
+        self.state
+            .sessions
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    pub fn send_message(&self, session_id: &str, message: Message) -> bool {
+        match self.state.sessions.lock().unwrap().get(session_id) {
+            Some(sender) => sender.send(message).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn send_json(&self, session_id: &str, message: serde_json::Value) -> bool {
+        self.send_message(session_id, Message::Text(message.to_string()))
+    }
+
+    pub fn send_keepalive(&self, session_id: &str) -> bool {
+        let message = eventsub_message(&mut self.fixtures(), "session_keepalive", None, json!({}));
+        self.send_json(session_id, message)
+    }
+
+    pub fn send_reconnect(&self, session_id: &str) -> bool {
+        let reconnect_url = format!("{0}?reconnect={session_id}", self.eventsub_url());
+        let message = eventsub_message(
+            &mut self.fixtures(),
+            "session_reconnect",
+            None,
+            json!({
+                "session": {
+                    "id": session_id,
+                    "status": "reconnecting",
+                    "connected_at": Utc::now().to_rfc3339(),
+                    "keepalive_timeout_seconds": null,
+                    "reconnect_url": reconnect_url,
+                }
+            }),
+        );
+        self.send_json(session_id, message)
+    }
+
+    pub fn send_notification(&self, subscription_type: &str, event: serde_json::Value) -> usize {
+        let messages: Vec<(String, serde_json::Value)> = {
+            let mut fixtures = self.fixtures();
+            let subscriptions: Vec<EventSub> = fixtures
+                .subscriptions
+                .iter()
+                .filter(|s| s.r#type == subscription_type && s.status == "enabled")
+                .cloned()
+                .collect();
+            subscriptions
+                .into_iter()
+                .filter_map(|subscription| {
+                    let session_id = match &subscription.transport.conduit_id {
+                        Some(conduit_id) => fixtures
+                            .conduit_shards
+                            .get(conduit_id)?
+                            .iter()
+                            .find(|s| s.status == "enabled")?
+                            .transport
+                            .session_id
+                            .clone()?,
+                        None => subscription.transport.session_id.clone()?,
+                    };
+                    let payload = json!({ "subscription": subscription, "event": event });
+                    let message = eventsub_message(
+                        &mut fixtures,
+                        "notification",
+                        Some(&subscription),
+                        payload,
+                    );
+                    Some((session_id, message))
+                })
+                .collect()
+        };
+
+        messages
+            .into_iter()
+            .filter(|(session_id, message)| self.send_json(session_id, message.clone()))
+            .count()
+    }
+
+    pub fn send_revocation(&self, subscription_id: &str, status: &str) -> bool {
+        let (session_id, message) = {
+            let mut fixtures = self.fixtures();
+            let subscription = match fixtures
+                .subscriptions
+                .iter_mut()
+                .find(|s| s.id == subscription_id)
+            {
+                Some(v) => {
+                    v.status = status.to_string();
+                    v.clone()
+                }
+                None => return false,
+            };
+            let session_id = match &subscription.transport.session_id {
+                Some(v) => v.clone(),
+                None => return false,
+            };
+            let payload = json!({ "subscription": subscription });
+            let message =
+                eventsub_message(&mut fixtures, "revocation", Some(&subscription), payload);
+            (session_id, message)
+        };
+
+        self.send_json(&session_id, message)
+    }
+
+    pub fn close_session(&self, session_id: &str, code: u16, reason: &str) -> bool {
+        self.send_message(
+            session_id,
+            Message::Close(Some(CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.to_string().into(),
+            })),
+        )
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn eventsub_message(
+    fixtures: &mut Fixtures,
+    message_type: &str,
+    subscription: Option<&EventSub>,
+    payload: serde_json::Value,
+) -> serde_json::Value {
+    let mut metadata = json!({
+        "message_id": fixtures.next_id("message"),
+        "message_type": message_type,
+        "message_timestamp": Utc::now().to_rfc3339(),
+    });
+    if let Some(subscription) = subscription {
+        metadata["subscription_type"] = json!(subscription.r#type);
+        metadata["subscription_version"] = json!(subscription.version);
+    }
+
+    json!({ "metadata": metadata, "payload": payload })
+}
+
+async fn serve_eventsub(listener: tokio::net::TcpListener, state: MockState) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((v, _)) => v,
+            Err(e) => {
+                log::error!("Mock EventSub server failed: {e}");
+                return;
+            }
+        };
+
+        if state.fixtures.lock().unwrap().eventsub_unavailable {
+            continue;
+        }
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = eventsub_session(stream, state).await {
+                log::debug!("Mock EventSub session ended: {e}");
+            }
+        });
+    }
+}
+
+#[allow(clippy::result_large_err)]
+async fn eventsub_session(
+    stream: tokio::net::TcpStream,
+    state: MockState,
+) -> std::result::Result<(), tokio_tungstenite::tungstenite::Error> {
+    let mut query = None;
+    let ws_stream = tokio_tungstenite::accept_hdr_async(
+        stream,
+        |req: &tokio_tungstenite::tungstenite::handshake::server::Request,
+         res: tokio_tungstenite::tungstenite::handshake::server::Response| {
+            query = req.uri().query().map(|q| q.to_string());
+            Ok(res)
+        },
+    )
+    .await?;
+    let reconnect = value(&form(&query.unwrap_or_default()), "reconnect");
+
+    let (mut sink, mut stream) = ws_stream.split();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let (session_id, previous, welcome) = {
+        //Always lock fixtures before sessions, like the Helix handlers do.
+        let mut fixtures = state.fixtures.lock().unwrap();
+        let mut sessions = state.sessions.lock().unwrap();
+        let session_id = fixtures.next_id("session");
+
+        //Register before the welcome so the client can subscribe as soon as it has the session id.
+        sessions.insert(session_id.clone(), sender.clone());
+        let previous = match reconnect {
+            Some(previous_id) => {
+                //A reconnected session gets a new id and takes over the old one's subscriptions.
+                let shards = fixtures.conduit_shards.values_mut().flatten();
+                for shard in shards {
+                    if shard.transport.session_id.as_ref() == Some(&previous_id) {
+                        shard.transport.session_id = Some(session_id.clone());
+                    }
+                }
+                for subscription in fixtures.subscriptions.iter_mut() {
+                    if subscription.transport.session_id.as_ref() == Some(&previous_id) {
+                        subscription.transport.session_id = Some(session_id.clone());
+                    }
+                }
+                match fixtures.close_reconnected_sessions {
+                    true => sessions.remove(&previous_id),
+                    false => None,
+                }
+            }
+            None => None,
+        };
+
+        let keepalive_timeout_seconds = fixtures.keepalive_timeout_seconds;
+        let welcome = eventsub_message(
+            &mut fixtures,
+            "session_welcome",
+            None,
+            json!({
+                "session": {
+                    "id": session_id,
+                    "status": "connected",
+                    "connected_at": Utc::now().to_rfc3339(),
+                    "keepalive_timeout_seconds": keepalive_timeout_seconds,
+                    "reconnect_url": null,
+                }
+            }),
+        );
+        (session_id, previous, welcome)
+    };
+
+    sink.send(Message::Text(welcome.to_string())).await?;
+    if let Some(previous) = previous {
+        //The old connection of a reconnecting session is closed once the new one is welcomed.
+        let _ = previous.send(Message::Close(None));
+    }
+
+    let result = loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(message) => {
+                    let close = matches!(message, Message::Close(..));
+                    if let Err(e) = sink.send(message).await {
+                        break Err(e);
+                    }
+                    if close {
+                        break Ok(());
+                    }
+                }
+                None => break Ok(()),
+            },
+            message = stream.next() => match message {
+                Some(Ok(Message::Close(..))) | None => break Ok(()),
+                Some(Ok(..)) => {}
+                Some(Err(e)) => break Err(e),
+            },
+        }
+    };
+
+    //Always lock fixtures before sessions, like the Helix handlers do.
+    let mut fixtures = state.fixtures.lock().unwrap();
+    let mut sessions = state.sessions.lock().unwrap();
+    if sessions
+        .get(&session_id)
+        .is_some_and(|s| s.same_channel(&sender))
+    {
+        sessions.remove(&session_id);
+        let shards = fixtures.conduit_shards.values_mut().flatten();
+        for shard in shards {
+            if shard.transport.session_id.as_ref() == Some(&session_id) {
+                shard.status = "websocket_disconnected".to_string();
+            }
+        }
+        for subscription in fixtures.subscriptions.iter_mut() {
+            if subscription.transport.session_id.as_ref() == Some(&session_id) {
+                subscription.status = "websocket_disconnected".to_string();
+            }
+        }
+    }
+
+    result
+}
+
+fn router(state: MockState) -> Router {
+    Router::new()
+        .route("/oauth2/token", post(oauth_token))
+        .route("/oauth2/validate", get(oauth_validate))
+        .route("/helix/users", get(get_users))
+        .route("/helix/streams", get(get_streams))
+        .route("/helix/channels/followers", get(get_channel_followers))
+        .route(
+            "/helix/channel_points/custom_rewards",
+            get(get_custom_rewards)
+                .post(create_custom_reward)
+                .patch(update_custom_reward)
+                .delete(delete_custom_reward),
+        )
+        .route(
+            "/helix/eventsub/subscriptions",
+            get(get_eventsub_subscriptions)
+                .post(create_eventsub_subscription)
+                .delete(delete_eventsub_subscription),
+        )
+        .route(
+            "/helix/eventsub/conduits",
+            get(get_conduits)
+                .post(create_conduit)
+                .patch(update_conduit)
+                .delete(delete_conduit),
+        )
+        .route(
+            "/helix/eventsub/conduits/shards",
+            get(get_conduit_shards).patch(update_conduit_shards),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.fixtures.clone(),
+            rate_limit,
+        ))
+        .with_state(state)
+}
+
+async fn rate_limit(
+    State(fixtures): State<SharedFixtures>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    if !request.uri().path().starts_with("/helix") {
+        return next.run(request).await;
+    }
+
+    let (limit, remaining, limited) = {
+        let mut fixtures = fixtures.lock().unwrap();
+        let limited = fixtures.rate_limited_requests > 0;
+        if limited {
+            fixtures.rate_limited_requests -= 1;
+        }
+        fixtures.rate_limit_remaining = fixtures.rate_limit_remaining.saturating_sub(1);
+        let remaining = if limited {
+            0
+        } else {
+            fixtures.rate_limit_remaining
+        };
+        (fixtures.rate_limit, remaining, limited)
+    };
+
+    let mut response = if limited {
+        twitch_error(StatusCode::TOO_MANY_REQUESTS, "Too Many Requests")
+    } else {
+        next.run(request).await
+    };
+    //Reset at the current second so a rate limited client only has to wait briefly.
+    let reset = if limited {
+        Utc::now().timestamp()
+    } else {
+        Utc::now().timestamp() + 60
+    };
+    let headers = response.headers_mut();
+    headers.insert("Ratelimit-Limit", limit.into());
+    headers.insert("Ratelimit-Remaining", remaining.into());
+    headers.insert("Ratelimit-Reset", reset.into());
+    response
+}
+
+fn twitch_error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "message": message,
+        })),
+    )
+        .into_response()
+}
+
+fn query(uri: &Uri) -> Vec<(String, String)> {
+    form(uri.query().unwrap_or_default())
+}
+
+fn form(data: &str) -> Vec<(String, String)> {
+    data.split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn values(pairs: &[(String, String)], key: &str) -> Vec<String> {
+    pairs
+        .iter()
+        .filter(|(k, _)| k == key)
+        .map(|(_, v)| v.clone())
+        .collect()
+}
+
+fn value(pairs: &[(String, String)], key: &str) -> Option<String> {
+    values(pairs, key).into_iter().next()
+}
+
+#[allow(clippy::result_large_err)]
+fn authorize(fixtures: &Fixtures, headers: &HeaderMap) -> std::result::Result<(), Response> {
+    let token = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer ").or(v.strip_prefix("OAuth ")));
+    if token != Some(fixtures.access_token.as_str()) {
+        return Err(twitch_error(
+            StatusCode::UNAUTHORIZED,
+            "Invalid OAuth token",
+        ));
+    }
+
+    let client_id = headers.get("Client-Id").and_then(|v| v.to_str().ok());
+    if client_id != Some(fixtures.client_id.as_str()) {
+        return Err(twitch_error(
+            StatusCode::UNAUTHORIZED,
+            "Client ID and OAuth token do not match",
+        ));
+    }
+
+    Ok(())
+}
+
+fn page<T: serde::Serialize>(items: Vec<T>, pairs: &[(String, String)]) -> Response {
+    let first = value(pairs, "first")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(20);
+    let start = value(pairs, "after")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let end = (start + first).min(items.len());
+    let total = items.len();
+    let data: Vec<T> = items.into_iter().skip(start).take(first).collect();
+    let pagination = if end < total {
+        json!({ "cursor": end.to_string() })
+    } else {
+        json!({})
+    };
+
+    Json(json!({ "data": data, "pagination": pagination, "total": total })).into_response()
+}
+
+async fn oauth_token(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    body: String,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    let pairs = form(&body);
+
+    if value(&pairs, "client_id").as_ref() != Some(&fixtures.client_id)
+        || value(&pairs, "client_secret").as_ref() != Some(&fixtures.client_secret)
+    {
+        return Err(twitch_error(StatusCode::FORBIDDEN, "invalid client secret"));
+    }
+
+    match value(&pairs, "grant_type").as_deref() {
+        Some("refresh_token") => {
+            if value(&pairs, "refresh_token").as_ref() != Some(&fixtures.refresh_token) {
+                return Err(twitch_error(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid refresh token",
+                ));
+            }
+        }
+        Some("client_credentials") | Some("authorization_code") => {}
+        _ => return Err(twitch_error(StatusCode::BAD_REQUEST, "Invalid grant type")),
+    }
+
+    fixtures.token_requests += 1;
+    fixtures.token_user_agent = headers
+        .get("User-Agent")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    fixtures.access_token = fixtures.next_id("mock-access-token");
+    fixtures.refresh_token = fixtures.next_id("mock-refresh-token");
+
+    Ok(Json(json!({
+        "access_token": fixtures.access_token,
+        "refresh_token": fixtures.refresh_token,
+        "expires_in": 14400,
+        "scope": fixtures.scopes,
+        "token_type": "bearer",
+    }))
+    .into_response())
+}
+
+async fn oauth_validate(State(fixtures): State<SharedFixtures>, headers: HeaderMap) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let user = fixtures.token_user();
+    Ok(Json(json!({
+        "client_id": fixtures.client_id,
+        "login": user.map(|u| u.login.clone()),
+        "scopes": fixtures.scopes,
+        "user_id": user.map(|u| u.id.clone()),
+        "expires_in": 14400,
+    }))
+    .into_response())
+}
+
+async fn get_users(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    let ids = values(&pairs, "id");
+    let logins = values(&pairs, "login");
+
+    let users: Vec<&User> = if ids.is_empty() && logins.is_empty() {
+        fixtures.token_user().into_iter().collect()
+    } else {
+        fixtures
+            .users
+            .iter()
+            .filter(|u| ids.contains(&u.id) || logins.contains(&u.login))
+            .collect()
+    };
+
+    Ok(Json(json!({ "data": users })).into_response())
+}
+
+async fn get_streams(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    let user_ids = values(&pairs, "user_id");
+    let user_logins = values(&pairs, "user_login");
+    let game_ids = values(&pairs, "game_id");
+    let languages = values(&pairs, "language");
+
+    let streams: Vec<&Stream> = fixtures
+        .streams
+        .iter()
+        .filter(|s| user_ids.is_empty() || user_ids.contains(&s.user_id))
+        .filter(|s| user_logins.is_empty() || user_logins.contains(&s.user_login))
+        .filter(|s| game_ids.is_empty() || game_ids.contains(&s.game_id))
+        .filter(|s| languages.is_empty() || languages.contains(&s.language))
+        .collect();
+
+    Ok(page(streams, &pairs))
+}
+
+async fn get_channel_followers(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    let broadcaster_id = value(&pairs, "broadcaster_id").ok_or_else(|| {
+        twitch_error(
+            StatusCode::BAD_REQUEST,
+            "Missing required parameter \"broadcaster_id\"",
+        )
+    })?;
+    let user_id = value(&pairs, "user_id");
+
+    let followers: Vec<&ChannelFollowers> = fixtures
+        .followers
+        .get(&broadcaster_id)
+        .into_iter()
+        .flatten()
+        .filter(|f| user_id.is_none() || user_id.as_ref() == Some(&f.user_id))
+        .collect();
+
+    Ok(page(followers, &pairs))
+}
+
+#[allow(clippy::result_large_err)]
+fn check_broadcaster(
+    fixtures: &Fixtures,
+    pairs: &[(String, String)],
+) -> std::result::Result<(), Response> {
+    match value(pairs, "broadcaster_id") {
+        Some(id) if id == fixtures.user_id => Ok(()),
+        Some(..) => Err(twitch_error(
+            StatusCode::FORBIDDEN,
+            "The ID in broadcaster_id must match the user ID found in the request's OAuth token.",
+        )),
+        None => Err(twitch_error(
+            StatusCode::BAD_REQUEST,
+            "Missing required parameter \"broadcaster_id\"",
+        )),
+    }
+}
+
+async fn get_custom_rewards(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    check_broadcaster(&fixtures, &pairs)?;
+    let ids = values(&pairs, "id");
+
+    let rewards: Vec<&Reward> = fixtures
+        .rewards
+        .iter()
+        .filter(|r| ids.is_empty() || ids.contains(&r.id))
+        .collect();
+
+    if !ids.is_empty() && rewards.is_empty() {
+        return Err(twitch_error(
+            StatusCode::NOT_FOUND,
+            "Custom Reward not found",
+        ));
+    }
+
+    Ok(Json(json!({ "data": rewards })).into_response())
+}
+
+async fn create_custom_reward(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+    Json(create): Json<RewardCreate>,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+    check_broadcaster(&fixtures, &query(&uri))?;
+
+    if fixtures.rewards.iter().any(|r| r.title == create.title) {
+        return Err(twitch_error(
+            StatusCode::BAD_REQUEST,
+            "CREATE_CUSTOM_REWARD_DUPLICATE_REWARD",
+        ));
+    }
+
+    let broadcaster = match fixtures.token_user() {
+        Some(v) => v.clone(),
+        None => return Err(twitch_error(StatusCode::NOT_FOUND, "User not found")),
+    };
+    let id = fixtures.next_id("reward");
+    let mut reward = Fixtures::reward(&broadcaster, &id, &create.title, create.cost);
+    reward.prompt = create.prompt.unwrap_or_default();
+    reward.is_enabled = create.is_enabled.unwrap_or(true);
+    if let Some(background_color) = create.background_color {
+        reward.background_color = background_color;
+    }
+    reward.is_user_input_required = create.is_user_input_required.unwrap_or_default();
+    reward.should_redemptions_skip_request_queue = create
+        .should_redemptions_skip_request_queue
+        .unwrap_or_default();
+    fixtures.rewards.push(reward.clone());
+
+    Ok(Json(json!({ "data": [reward] })).into_response())
+}
+
+async fn update_custom_reward(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+    Json(update): Json<RewardUpdate>,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    check_broadcaster(&fixtures, &pairs)?;
+    let id = value(&pairs, "id");
+
+    let reward = match fixtures
+        .rewards
+        .iter_mut()
+        .find(|r| Some(&r.id) == id.as_ref())
+    {
+        Some(v) => v,
+        None => {
+            return Err(twitch_error(
+                StatusCode::NOT_FOUND,
+                "Custom Reward not found",
+            ))
+        }
+    };
+    if let Some(title) = update.title {
+        reward.title = title;
+    }
+    if let Some(cost) = update.cost {
+        reward.cost = cost;
+    }
+    if let Some(prompt) = update.prompt {
+        reward.prompt = prompt;
+    }
+    if let Some(is_enabled) = update.is_enabled {
+        reward.is_enabled = is_enabled;
+    }
+    if let Some(is_paused) = update.is_paused {
+        reward.is_paused = is_paused;
+    }
+    if let Some(background_color) = update.background_color {
+        reward.background_color = background_color;
+    }
+
+    Ok(Json(json!({ "data": [reward] })).into_response())
+}
+
+async fn delete_custom_reward(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    check_broadcaster(&fixtures, &pairs)?;
+    let id = value(&pairs, "id");
+
+    let count = fixtures.rewards.len();
+    fixtures.rewards.retain(|r| Some(&r.id) != id.as_ref());
+    if fixtures.rewards.len() == count {
+        return Err(twitch_error(
+            StatusCode::NOT_FOUND,
+            "Custom Reward not found",
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+fn subscriptions_response(fixtures: &Fixtures, data: Vec<&EventSub>) -> Json<serde_json::Value> {
+    //Like Twitch, total and total_cost cover all subscriptions regardless of filters.
+    Json(json!({
+        "data": data,
+        "total": fixtures.subscriptions.len(),
+        "total_cost": fixtures.subscriptions.iter().map(|s| s.cost).sum::<i64>(),
+        "max_total_cost": 10000,
+        "pagination": {},
+    }))
+}
+
+async fn get_eventsub_subscriptions(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    let status = value(&pairs, "status");
+    let r#type = value(&pairs, "type");
+    let user_id = value(&pairs, "user_id");
+    if [&status, &r#type, &user_id]
+        .iter()
+        .filter(|v| v.is_some())
+        .count()
+        > 1
+    {
+        return Err(twitch_error(
+            StatusCode::BAD_REQUEST,
+            "only one of status, type or user_id may be specified",
+        ));
+    }
+    let subscriptions: Vec<&EventSub> = fixtures
+        .subscriptions
+        .iter()
+        .filter(|s| status.is_none() || status.as_ref() == Some(&s.status))
+        .filter(|s| r#type.is_none() || r#type.as_ref() == Some(&s.r#type))
+        .filter(|s| {
+            let condition = &s.condition;
+            user_id.is_none()
+                || [
+                    &condition.broadcaster_user_id,
+                    &condition.moderator_user_id,
+                    &condition.user_id,
+                    &condition.from_broadcaster_user_id,
+                    &condition.to_broadcaster_user_id,
+                ]
+                .contains(&&user_id)
+        })
+        .collect();
+
+    let first = value(&pairs, "first")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(100);
+    let start = value(&pairs, "after")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let end = (start + first).min(subscriptions.len());
+    let mut response = subscriptions_response(
+        &fixtures,
+        subscriptions.get(start..end).unwrap_or_default().to_vec(),
+    );
+    if end < subscriptions.len() {
+        response["pagination"] = json!({ "cursor": end.to_string() });
+    }
+
+    Ok(response.into_response())
+}
+
+async fn create_eventsub_subscription(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> MockResult {
+    let mut fixtures = state.fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let create: EventSubCreate = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Err(twitch_error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    if let Ok(subscription_type) = create.r#type.parse::<SubscriptionType>() {
+        if !subscription_type
+            .missing_scopes(&fixtures.scopes)
+            .is_empty()
+        {
+            return Err(twitch_error(
+                StatusCode::FORBIDDEN,
+                "subscription missing proper authorization",
+            ));
+        }
+    }
+
+    if create.transport.method == "websocket" {
+        let connected = match &create.transport.session_id {
+            Some(session_id) => state.sessions.lock().unwrap().contains_key(session_id),
+            None => false,
+        };
+        if !connected {
+            return Err(twitch_error(
+                StatusCode::BAD_REQUEST,
+                "websocket transport session does not exist or has already disconnected",
+            ));
+        }
+    }
+
+    if create.transport.method == "conduit"
+        && !fixtures
+            .conduits
+            .iter()
+            .any(|c| Some(&c.id) == create.transport.conduit_id.as_ref())
+    {
+        return Err(twitch_error(StatusCode::BAD_REQUEST, "conduit not found"));
+    }
+
+    let duplicate = fixtures.subscriptions.iter().any(|s| {
+        s.r#type == create.r#type
+            && s.version == create.version
+            && serde_json::to_value(&s.condition).ok()
+                == serde_json::to_value(&create.condition).ok()
+            && s.transport.session_id == create.transport.session_id
+            && s.transport.callback == create.transport.callback
+    });
+    if duplicate {
+        return Err(twitch_error(
+            StatusCode::CONFLICT,
+            "subscription already exists",
+        ));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut transport = create.transport;
+    if transport.method == "websocket" {
+        transport.connected_at = Some(now.clone());
+    }
+    let subscription = EventSub {
+        id: fixtures.next_id("subscription"),
+        status: "enabled".to_string(),
+        r#type: create.r#type,
+        version: create.version,
+        condition: create.condition,
+        created_at: now,
+        transport,
+        cost: 0,
+    };
+    fixtures.subscriptions.push(subscription.clone());
+
+    Ok((
+        StatusCode::ACCEPTED,
+        subscriptions_response(&fixtures, vec![&subscription]),
+    )
+        .into_response())
+}
+
+async fn delete_eventsub_subscription(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let id = value(&query(&uri), "id");
+    let count = fixtures.subscriptions.len();
+    fixtures
+        .subscriptions
+        .retain(|s| Some(&s.id) != id.as_ref());
+    if fixtures.subscriptions.len() == count {
+        return Err(twitch_error(
+            StatusCode::NOT_FOUND,
+            "subscription not found",
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn get_conduits(State(fixtures): State<SharedFixtures>, headers: HeaderMap) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    Ok(Json(json!({ "data": fixtures.conduits })).into_response())
+}
+
+fn conduit_shards(conduit: &Conduit) -> Vec<ConduitShard> {
+    (0..conduit.shard_count)
+        .map(|id| ConduitShard {
+            id: id.to_string(),
+            status: "disabled".to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+async fn create_conduit(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let create: ConduitCreate = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Err(twitch_error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    let conduit = Conduit {
+        id: fixtures.next_id("conduit"),
+        shard_count: create.shard_count,
+    };
+    let shards = conduit_shards(&conduit);
+    fixtures.conduit_shards.insert(conduit.id.clone(), shards);
+    fixtures.conduits.push(conduit.clone());
+
+    Ok(Json(json!({ "data": [conduit] })).into_response())
+}
+
+async fn update_conduit(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let update: ConduitUpdate = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Err(twitch_error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    let conduit = match fixtures.conduits.iter_mut().find(|c| c.id == update.id) {
+        Some(v) => {
+            v.shard_count = update.shard_count;
+            v.clone()
+        }
+        None => return Err(twitch_error(StatusCode::NOT_FOUND, "conduit not found")),
+    };
+    let mut shards = fixtures
+        .conduit_shards
+        .remove(&conduit.id)
+        .unwrap_or_default();
+    shards.truncate(conduit.shard_count.max(0) as usize);
+    shards.extend(conduit_shards(&conduit).into_iter().skip(shards.len()));
+    fixtures.conduit_shards.insert(conduit.id.clone(), shards);
+
+    Ok(Json(json!({ "data": [conduit] })).into_response())
+}
+
+async fn delete_conduit(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let mut fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let id = value(&query(&uri), "id").unwrap_or_default();
+    let count = fixtures.conduits.len();
+    fixtures.conduits.retain(|c| c.id != id);
+    if fixtures.conduits.len() == count {
+        return Err(twitch_error(StatusCode::NOT_FOUND, "conduit not found"));
+    }
+    fixtures.conduit_shards.remove(&id);
+    fixtures
+        .subscriptions
+        .retain(|s| s.transport.conduit_id.as_ref() != Some(&id));
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn get_conduit_shards(
+    State(fixtures): State<SharedFixtures>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> MockResult {
+    let fixtures = fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let pairs = query(&uri);
+    let shards = match value(&pairs, "conduit_id").and_then(|id| fixtures.conduit_shards.get(&id)) {
+        Some(v) => v,
+        None => return Err(twitch_error(StatusCode::NOT_FOUND, "conduit not found")),
+    };
+    let status = value(&pairs, "status");
+    let shards: Vec<&ConduitShard> = shards
+        .iter()
+        .filter(|s| status.is_none() || status.as_ref() == Some(&s.status))
+        .collect();
+
+    Ok(page(shards, &pairs))
+}
+
+async fn update_conduit_shards(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> MockResult {
+    let mut fixtures = state.fixtures.lock().unwrap();
+    authorize(&fixtures, &headers)?;
+
+    let update: ConduitShardsUpdate = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Err(twitch_error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    let sessions = state.sessions.lock().unwrap();
+    let shards = match fixtures.conduit_shards.get_mut(&update.conduit_id) {
+        Some(v) => v,
+        None => return Err(twitch_error(StatusCode::NOT_FOUND, "conduit not found")),
+    };
+
+    let mut data = Vec::new();
+    let mut errors = Vec::new();
+    for update in update.shards {
+        let error = |message: &str, code: &str| ConduitShardError {
+            id: update.id.clone(),
+            message: message.to_string(),
+            code: code.to_string(),
+        };
+
+        let shard = match shards.iter_mut().find(|s| s.id == update.id) {
+            Some(v) => v,
+            None => {
+                errors.push(error("shard not found", "shard_not_found"));
+                continue;
+            }
+        };
+
+        let mut transport = update.transport;
+        if transport.method == "websocket" {
+            match &transport.session_id {
+                Some(session_id) if sessions.contains_key(session_id) => {}
+                _ => {
+                    errors.push(error(
+                        "websocket session does not exist",
+                        "websocket_session_not_found",
+                    ));
+                    continue;
+                }
+            }
+            transport.connected_at = Some(Utc::now().to_rfc3339());
+        }
+
+        shard.status = "enabled".to_string();
+        shard.transport = transport;
+        data.push(shard.clone());
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "data": data, "errors": errors })),
+    )
+        .into_response())
+}
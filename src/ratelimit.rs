@@ -0,0 +1,179 @@
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::HeaderMap;
+use std::sync::{Arc, Mutex};
+
+const THROTTLE_FRACTION: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<i64>().ok();
+
+        Some(RateLimit {
+            limit: header("Ratelimit-Limit")?.try_into().ok()?,
+            remaining: header("Ratelimit-Remaining")?.try_into().ok()?,
+            reset: DateTime::<Utc>::from_timestamp(header("Ratelimit-Reset")?, 0)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Option<RateLimit>>>,
+}
+
+impl RateLimiter {
+    pub fn get(&self) -> Option<RateLimit> {
+        *self.bucket.lock().unwrap()
+    }
+
+    pub(crate) fn update(&self, headers: &HeaderMap) {
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            self.bucket.lock().unwrap().replace(rate_limit);
+        }
+    }
+
+    pub(crate) fn exhaust(&self) {
+        let now = Utc::now();
+        let mut bucket = self.bucket.lock().unwrap();
+        let bucket = bucket.get_or_insert(RateLimit {
+            limit: 1,
+            remaining: 0,
+            reset: now,
+        });
+        bucket.remaining = 0;
+        if bucket.reset <= now {
+            bucket.reset = now + Duration::seconds(1);
+        }
+    }
+
+    //Takes a point from the bucket, returning how long to wait before sending. Once fewer than
+    //1/THROTTLE_FRACTION of the points are left the remaining ones are spread over the time until
+    //the reset instead of being used up at once. Errs with the time until the reset when the
+    //bucket is empty.
+    fn reserve(&self, now: DateTime<Utc>) -> std::result::Result<Duration, Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let bucket = match bucket.as_mut() {
+            Some(v) => v,
+            None => return Ok(Duration::zero()),
+        };
+
+        if bucket.reset <= now {
+            //Bucket is full again, assume it takes another minute to drain.
+            bucket.remaining = bucket.limit;
+            bucket.reset = now + Duration::minutes(1);
+        }
+
+        if bucket.remaining == 0 {
+            return Err(bucket.reset - now);
+        }
+
+        bucket.remaining -= 1;
+        if bucket.remaining >= bucket.limit / THROTTLE_FRACTION {
+            return Ok(Duration::zero());
+        }
+        Ok((bucket.reset - now) / (bucket.remaining as i32 + 1))
+    }
+
+    pub(crate) async fn acquire(&self) {
+        loop {
+            match self.reserve(Utc::now()) {
+                Ok(wait) => {
+                    if wait > Duration::zero() {
+                        log::debug!("Rate limit running low, waiting {wait}");
+                        tokio::time::sleep(wait.to_std().unwrap_or_default()).await;
+                    }
+                    return;
+                }
+                Err(wait) => {
+                    log::debug!("Rate limit exhausted, waiting {wait}");
+                    tokio::time::sleep(wait.to_std().unwrap_or_default()).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(limit: &str, remaining: &str, reset: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Ratelimit-Limit", limit.parse().unwrap());
+        headers.insert("Ratelimit-Remaining", remaining.parse().unwrap());
+        headers.insert("Ratelimit-Reset", reset.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_headers() {
+        let rate_limit = RateLimit::from_headers(&headers("800", "799", "1700000000")).unwrap();
+        assert_eq!(rate_limit.limit, 800);
+        assert_eq!(rate_limit.remaining, 799);
+        assert_eq!(rate_limit.reset.timestamp(), 1700000000);
+
+        assert!(RateLimit::from_headers(&headers("800", "-1", "1700000000")).is_none());
+        assert!(RateLimit::from_headers(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn throttles_before_the_bucket_is_empty() {
+        let now = Utc::now();
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.reserve(now), Ok(Duration::zero()));
+
+        limiter.bucket.lock().unwrap().replace(RateLimit {
+            limit: 100,
+            remaining: 12,
+            reset: now + Duration::seconds(10),
+        });
+        assert_eq!(limiter.reserve(now), Ok(Duration::zero()));
+        assert_eq!(limiter.reserve(now), Ok(Duration::zero()));
+        //Below 10 left, the rest are spread over the remaining 10 seconds.
+        assert_eq!(limiter.reserve(now), Ok(Duration::seconds(1)));
+        assert_eq!(limiter.get().unwrap().remaining, 9);
+
+        limiter.bucket.lock().unwrap().as_mut().unwrap().remaining = 0;
+        assert_eq!(limiter.reserve(now), Err(Duration::seconds(10)));
+
+        //Once the reset has passed the bucket is refilled.
+        assert_eq!(
+            limiter.reserve(now + Duration::seconds(11)),
+            Ok(Duration::zero())
+        );
+        assert_eq!(limiter.get().unwrap().remaining, 99);
+    }
+
+    #[test]
+    fn exhaust_waits_at_least_a_second() {
+        let limiter = RateLimiter::default();
+        limiter.exhaust();
+        let now = Utc::now();
+        match limiter.reserve(now) {
+            Err(wait) => assert!(wait > Duration::zero() && wait <= Duration::seconds(1)),
+            r => panic!("unexpected reservation: {r:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_the_reset() {
+        let limiter = RateLimiter::default();
+        limiter.bucket.lock().unwrap().replace(RateLimit {
+            limit: 10,
+            remaining: 0,
+            reset: Utc::now() + Duration::milliseconds(200),
+        });
+
+        let start = std::time::Instant::now();
+        limiter.acquire().await;
+        assert!(start.elapsed() >= std::time::Duration::from_millis(150));
+        assert_eq!(limiter.get().unwrap().remaining, 9);
+    }
+}
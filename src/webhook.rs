@@ -0,0 +1,155 @@
+use crate::error::{Error, Result};
+use crate::eventsub::{parse_envelope, Deduplicator, Envelope, MessageMetadata};
+
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Challenge {
+    pub challenge: String,
+    pub subscription: crate::helix::EventSub,
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    secret: String,
+    deduplicator: Arc<Mutex<Deduplicator>>,
+    sender: UnboundedSender<Envelope>,
+}
+
+impl Webhook {
+    pub fn new(secret: String) -> (Webhook, UnboundedReceiver<Envelope>) {
+        let (sender, receiver) = unbounded_channel();
+        let webhook = Webhook {
+            secret,
+            deduplicator: Default::default(),
+            sender,
+        };
+        (webhook, receiver)
+    }
+
+    pub fn with_deduplicator(mut self, deduplicator: Deduplicator) -> Webhook {
+        self.deduplicator = Arc::new(Mutex::new(deduplicator));
+        self
+    }
+
+    fn mac(&self, message_id: &str, timestamp: &str, body: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(message_id.as_bytes());
+        mac.update(timestamp.as_bytes());
+        mac.update(body);
+        mac
+    }
+
+    pub fn signature(&self, message_id: &str, timestamp: &str, body: &[u8]) -> String {
+        let signature = self.mac(message_id, timestamp, body).finalize();
+        format!("sha256={0}", hex::encode(signature.into_bytes()))
+    }
+
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<()> {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        let (message_id, timestamp, signature) = match (
+            header("Twitch-Eventsub-Message-Id"),
+            header("Twitch-Eventsub-Message-Timestamp"),
+            header("Twitch-Eventsub-Message-Signature"),
+        ) {
+            (Some(id), Some(timestamp), Some(signature)) => (id, timestamp, signature),
+            _ => return Err(Error::InvalidSignature),
+        };
+
+        let signature = signature
+            .strip_prefix("sha256=")
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or(Error::InvalidSignature)?;
+
+        self.mac(message_id, timestamp, body)
+            .verify_slice(&signature)
+            .map_err(|_| Error::InvalidSignature)
+    }
+
+    //Answers a request to the callback url, for any framework built on the http crate.
+    pub fn handle<B: AsRef<[u8]>>(&self, request: http::Request<B>) -> http::Response<String> {
+        let (status, body) = self.respond(request.headers(), request.body().as_ref());
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+        response
+    }
+
+    //The same as handle for frameworks that extract headers and body separately. With axum:
+    //post(move |headers: HeaderMap, body: Bytes| async move { webhook.respond(&headers, &body) })
+    pub fn respond(&self, headers: &HeaderMap, body: &[u8]) -> (StatusCode, String) {
+        if let Err(e) = self.verify(headers, body) {
+            log::warn!("Rejecting webhook: {e}");
+            return (StatusCode::FORBIDDEN, e.to_string());
+        }
+
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let message_type = header("Twitch-Eventsub-Message-Type");
+
+        let payload: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        let notification = match message_type {
+            "webhook_callback_verification" => {
+                return match serde_json::from_value::<Challenge>(payload) {
+                    Ok(v) => {
+                        log::debug!("Verified webhook for {}", v.subscription.r#type);
+                        (StatusCode::OK, v.challenge)
+                    }
+                    Err(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+                };
+            }
+            "notification" | "revocation" => {
+                let metadata = MessageMetadata {
+                    message_id: header("Twitch-Eventsub-Message-Id").to_string(),
+                    message_timestamp: header("Twitch-Eventsub-Message-Timestamp").to_string(),
+                    message_type: message_type.to_string(),
+                    subscription_type: Some(
+                        header("Twitch-Eventsub-Subscription-Type").to_string(),
+                    ),
+                    subscription_version: Some(
+                        header("Twitch-Eventsub-Subscription-Version").to_string(),
+                    ),
+                };
+                parse_envelope(metadata, payload)
+            }
+            _ => return (StatusCode::BAD_REQUEST, "Unknown message type".to_string()),
+        };
+
+        match notification {
+            Ok(v) => {
+                let mut deduplicator = self.deduplicator.lock().unwrap();
+                if !deduplicator.is_recent(&v.metadata.message_id, &v.metadata.message_timestamp) {
+                    return (StatusCode::BAD_REQUEST, "Message is too old".to_string());
+                }
+                //Twitch retries until it gets a 2xx, so replays are acknowledged but not forwarded.
+                //Only messages that parsed are recorded, a retry of a rejected one goes through.
+                if !deduplicator.check(&v.metadata.message_id, &v.metadata.message_timestamp) {
+                    return (StatusCode::NO_CONTENT, String::new());
+                }
+                drop(deduplicator);
+                if self.sender.send(v).is_err() {
+                    log::warn!("Webhook receiver dropped");
+                }
+                (StatusCode::NO_CONTENT, String::new())
+            }
+            Err(e) => {
+                log::error!("Failed to parse webhook: {0}", e.error);
+                (StatusCode::BAD_REQUEST, e.error)
+            }
+        }
+    }
+}
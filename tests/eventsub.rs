@@ -0,0 +1,1383 @@
+use futures::StreamExt;
+use serde_json::json;
+use tw_api::eventsub::{
+    ChatEvent, ChatMessageFragment, Events, NotificationType, ReconnectEvent, SessionEvent,
+    Subscription, SubscriptionType,
+};
+use tw_api::helix::{EventSubCondition, EventSubCreate, EventSubFilter, EventSubTransport};
+use tw_api::mock::{Fixtures, MockServer};
+use tw_api::Error;
+
+#[tokio::test]
+async fn notifications_are_delivered_over_websocket() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    assert_eq!(server.sessions(), vec![eventsub.session_id.clone()]);
+    assert_eq!(
+        server.fixtures().subscriptions[0].transport.session_id,
+        Some(eventsub.session_id.clone())
+    );
+
+    let delivered = server.send_notification(
+        "stream.online",
+        json!({
+            "id": "9001",
+            "broadcaster_user_id": "1000",
+            "broadcaster_user_login": "mock_user",
+            "broadcaster_user_name": "Mock_User",
+            "type": "live",
+            "started_at": "2023-01-01T00:00:00Z",
+        }),
+    );
+    assert_eq!(delivered, 1);
+
+    match eventsub.next().await {
+        Some(NotificationType::StreamOnline(event)) => assert_eq!(event.id, "9001"),
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn websocket_subscriptions_require_a_session() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let err = client
+        .create_eventsub_subscription(&tw_api::helix::EventSubCreate {
+            r#type: "stream.online".to_string(),
+            version: "1".to_string(),
+            transport: tw_api::helix::EventSubTransport {
+                method: "websocket".to_string(),
+                session_id: Some("missing".to_string()),
+                ..Default::default()
+            },
+            condition: EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, tw_api::Error::Twitch(e) if e.status == 400));
+}
+
+#[tokio::test]
+async fn closing_the_session_ends_the_stream() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    assert!(server.close_session(&eventsub.session_id, 4001, "Client sent inbound traffic"));
+    assert!(eventsub.next().await.is_none());
+}
+
+fn stream_online(id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "type": "live",
+        "started_at": "2023-01-01T00:00:00Z",
+    })
+}
+
+//Returns the session id of a welcome that came first, if any, and the next notification's id.
+async fn next_notification(events: &mut Events) -> (Option<String>, String) {
+    let mut welcomed = None;
+    loop {
+        match events.next().await {
+            Some(SessionEvent::Welcome(welcome)) => welcomed = Some(welcome.session.id),
+            Some(SessionEvent::Notification(envelope)) => match envelope.event {
+                NotificationType::StreamOnline(event) => return (welcomed, event.id),
+                other => panic!("Unexpected notification: {other:?}"),
+            },
+            Some(SessionEvent::Reconnect(..) | SessionEvent::Closed { .. }) => {}
+            other => panic!("Unexpected event: {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn session_reconnect_keeps_the_stream_alive() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut events = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap()
+        .into_events();
+    let old_session = match events.next().await {
+        Some(SessionEvent::Welcome(welcome)) => welcome.session.id,
+        other => panic!("Unexpected event: {other:?}"),
+    };
+
+    //The client hasn't read the reconnect yet, so this one goes out on the old socket.
+    assert!(server.send_reconnect(&old_session));
+    assert_eq!(
+        server.send_notification("stream.online", stream_online("1")),
+        1
+    );
+    let (welcomed, id) = next_notification(&mut events).await;
+    assert_eq!(id, "1");
+
+    //Wait for the new session's welcome, everything after it is sent on the new socket.
+    let new_session = match welcomed {
+        Some(v) => v,
+        None => loop {
+            match events.next().await {
+                Some(SessionEvent::Welcome(welcome)) => break welcome.session.id,
+                Some(SessionEvent::Closed { .. }) => {}
+                other => panic!("Unexpected event: {other:?}"),
+            }
+        },
+    };
+    assert_ne!(new_session, old_session);
+    for id in ["2", "3", "4"] {
+        assert_eq!(
+            server.send_notification("stream.online", stream_online(id)),
+            1
+        );
+    }
+    for id in ["2", "3", "4"] {
+        assert_eq!(next_notification(&mut events).await, (None, id.to_string()));
+    }
+
+    assert_eq!(events.0.session_id, new_session);
+    assert_eq!(server.sessions(), vec![new_session.clone()]);
+    let subscription = server.fixtures().subscriptions[0].clone();
+    assert_eq!(subscription.status, "enabled");
+    assert_eq!(subscription.transport.session_id, Some(new_session));
+    assert!(events.0.last_error.is_none());
+}
+
+#[tokio::test]
+async fn silent_old_sessions_are_left_on_keepalive_timeout() {
+    let fixtures = Fixtures {
+        keepalive_timeout_seconds: 1,
+        close_reconnected_sessions: false,
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let mut events = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap()
+        .into_events();
+    let old_session = events.0.session_id.clone();
+    assert!(server.send_reconnect(&old_session));
+    loop {
+        match events.next().await {
+            Some(SessionEvent::Welcome(welcome)) if welcome.session.id != old_session => break,
+            Some(SessionEvent::Welcome(..) | SessionEvent::Reconnect(..)) => {}
+            other => panic!("Unexpected event: {other:?}"),
+        }
+    }
+
+    //The old socket stays open but silent, the client moves over once it times out.
+    assert_eq!(
+        server.send_notification("stream.online", stream_online("1")),
+        1
+    );
+    assert_eq!(
+        next_notification(&mut events).await,
+        (None, "1".to_string())
+    );
+    assert!(events.0.last_error.is_none());
+}
+
+#[tokio::test]
+async fn missing_keepalives_end_the_stream() {
+    let fixtures = Fixtures {
+        keepalive_timeout_seconds: 1,
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+    assert!(server.send_keepalive(&eventsub.session_id));
+
+    let started = std::time::Instant::now();
+    assert!(eventsub.next().await.is_none());
+    assert!(started.elapsed() >= std::time::Duration::from_millis(800));
+    assert!(matches!(
+        eventsub.last_error,
+        Some(Error::KeepaliveTimeout(timeout)) if timeout.as_secs() == 1
+    ));
+}
+
+#[tokio::test]
+async fn resilient_client_resubscribes_after_disconnect() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let (mut eventsub, mut events) = client
+        .connect_eventsub_resilient(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    let old_session = eventsub.session_id.clone();
+    let old_subscription = server.fixtures().subscriptions[0].id.clone();
+
+    assert!(server.close_session(&old_session, 4000, "Internal server error"));
+    let next = tokio::spawn(async move {
+        let event = eventsub.next().await;
+        (eventsub, event)
+    });
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::Disconnected(Error::ConnectionClosed))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::Reconnecting { attempt: 1, .. })
+    ));
+    let new_session = match events.recv().await {
+        Some(ReconnectEvent::Reconnected { session_id }) => session_id,
+        other => panic!("Unexpected event: {other:?}"),
+    };
+    assert_ne!(new_session, old_session);
+
+    {
+        let fixtures = server.fixtures();
+        assert_eq!(fixtures.subscriptions.len(), 1);
+        assert_ne!(fixtures.subscriptions[0].id, old_subscription);
+        assert_eq!(
+            fixtures.subscriptions[0].transport.session_id,
+            Some(new_session.clone())
+        );
+    }
+
+    let event = json!({
+        "id": "9001",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "type": "live",
+        "started_at": "2023-01-01T00:00:00Z",
+    });
+    assert_eq!(server.send_notification("stream.online", event), 1);
+
+    let (eventsub, event) = next.await.unwrap();
+    assert!(matches!(event, Some(NotificationType::StreamOnline(..))));
+    assert_eq!(eventsub.session_id, new_session);
+}
+
+#[tokio::test]
+async fn revocations_are_surfaced() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    let id = server.fixtures().subscriptions[0].id.clone();
+    assert!(server.send_revocation(&id, "authorization_revoked"));
+
+    match eventsub.next().await {
+        Some(NotificationType::Revocation(revocation)) => {
+            assert_eq!(revocation.subscription.id, id);
+            assert_eq!(revocation.subscription.r#type, "stream.online");
+            assert_eq!(revocation.subscription.status, "authorization_revoked");
+            assert_eq!(
+                revocation
+                    .subscription
+                    .condition
+                    .broadcaster_user_id
+                    .as_deref(),
+                Some("1000")
+            );
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn session_events_include_control_messages() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut events = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap()
+        .into_events();
+    let session_id = events.0.session_id.clone();
+
+    match events.next().await {
+        Some(SessionEvent::Welcome(welcome)) => assert_eq!(welcome.session.id, session_id),
+        other => panic!("Unexpected event: {other:?}"),
+    }
+
+    assert!(server.send_keepalive(&session_id));
+    assert!(matches!(events.next().await, Some(SessionEvent::Keepalive)));
+
+    assert!(server.close_session(&session_id, 4003, "Connection unused"));
+    match events.next().await {
+        Some(SessionEvent::Closed { code, reason }) => {
+            assert_eq!(code, Some(4003));
+            assert_eq!(reason, "Connection unused");
+        }
+        other => panic!("Unexpected event: {other:?}"),
+    }
+    assert!(events.next().await.is_none());
+}
+
+#[tokio::test]
+async fn duplicate_and_stale_messages_are_dropped() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_offline("1000".to_string())])
+        .await
+        .unwrap();
+    let subscription = server.fixtures().subscriptions[0].clone();
+    let message = |id: &str, timestamp: String| {
+        json!({
+            "metadata": {
+                "message_id": id,
+                "message_type": "notification",
+                "message_timestamp": timestamp,
+                "subscription_type": "stream.offline",
+                "subscription_version": "1",
+            },
+            "payload": {
+                "subscription": subscription,
+                "event": {
+                    "broadcaster_user_id": id,
+                    "broadcaster_user_login": "mock_user",
+                    "broadcaster_user_name": "Mock_User",
+                },
+            },
+        })
+    };
+
+    let now = chrono::Utc::now();
+    let stale = now - chrono::Duration::minutes(11);
+    for (id, timestamp) in [("a", now), ("a", now), ("b", stale), ("c", now)] {
+        assert!(server.send_json(&eventsub.session_id, message(id, timestamp.to_rfc3339())));
+    }
+
+    //A delivery that fails to parse doesn't block a good redelivery with the same id.
+    let mut broken = message("d", now.to_rfc3339());
+    broken["payload"]["subscription"] = json!(null);
+    assert!(server.send_json(&eventsub.session_id, broken));
+    assert!(server.send_json(&eventsub.session_id, message("d", now.to_rfc3339())));
+
+    for id in ["a", "c"] {
+        match eventsub.next().await {
+            Some(NotificationType::StreamOffline(event)) => {
+                assert_eq!(event.broadcaster_user_id, id)
+            }
+            other => panic!("Unexpected notification: {other:?}"),
+        }
+    }
+    assert!(matches!(
+        eventsub.next().await,
+        Some(NotificationType::Malformed(..))
+    ));
+    assert!(matches!(
+        eventsub.next().await,
+        Some(NotificationType::StreamOffline(event)) if event.broadcaster_user_id == "d"
+    ));
+}
+
+#[tokio::test]
+async fn subscriptions_carry_their_own_conditions() {
+    let fixtures = Fixtures {
+        scopes: vec![
+            "moderator:read:followers".to_string(),
+            "channel:manage:redemptions".to_string(),
+        ],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let _eventsub = client
+        .connect_eventsub(vec![
+            Subscription::channel_follow("1001".to_string(), "1000".to_string()),
+            Subscription::channel_raid_to("1000".to_string()),
+            Subscription::custom_reward_redemption_add(
+                "1000".to_string(),
+                Some("reward-1".to_string()),
+            ),
+        ])
+        .await
+        .unwrap();
+
+    let fixtures = server.fixtures();
+    let follow = &fixtures.subscriptions[0];
+    assert_eq!(
+        (follow.r#type.as_str(), follow.version.as_str()),
+        ("channel.follow", "2")
+    );
+    assert_eq!(
+        follow.condition.broadcaster_user_id.as_deref(),
+        Some("1001")
+    );
+    assert_eq!(follow.condition.moderator_user_id.as_deref(), Some("1000"));
+
+    let raid = &fixtures.subscriptions[1];
+    assert_eq!(
+        raid.condition.to_broadcaster_user_id.as_deref(),
+        Some("1000")
+    );
+    assert_eq!(raid.condition.broadcaster_user_id, None);
+
+    let redemption = &fixtures.subscriptions[2];
+    assert_eq!(redemption.condition.reward_id.as_deref(), Some("reward-1"));
+}
+
+#[tokio::test]
+async fn missing_scopes_are_reported_before_subscribing() {
+    let fixtures = Fixtures {
+        scopes: vec!["moderator:read:followers".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let err = client
+        .connect_eventsub(vec![
+            Subscription::channel_follow("1000".to_string(), "1000".to_string()),
+            Subscription::new(SubscriptionType::ChannelSubscribe, Default::default()),
+        ])
+        .await
+        .unwrap_err();
+    match err {
+        Error::MissingScopes {
+            subscription_type,
+            scopes,
+        } => {
+            assert_eq!(subscription_type, "channel.subscribe");
+            assert_eq!(scopes, vec!["channel:read:subscriptions"]);
+        }
+        other => panic!("Unexpected error: {other:?}"),
+    }
+    assert!(server.fixtures().subscriptions.is_empty());
+    assert_eq!(
+        "channel.channel_points_custom_reward_redemption.add"
+            .parse::<SubscriptionType>()
+            .unwrap(),
+        SubscriptionType::ChannelPointsCustomRewardRedemptionAdd
+    );
+    assert!("stream.onlin".parse::<SubscriptionType>().is_err());
+}
+
+#[tokio::test]
+async fn unknown_types_and_invalid_conditions_are_rejected() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut typo = Subscription::stream_online("1000".to_string());
+    typo.r#type = "stream.onlin".to_string();
+    match client.connect_eventsub(vec![typo]).await {
+        Err(Error::UnknownSubscriptionType(v)) => assert_eq!(v, "stream.onlin"),
+        other => panic!("Unexpected result: {other:?}"),
+    }
+
+    let invalid = [
+        (
+            SubscriptionType::ChannelFollow,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+            "missing moderator_user_id",
+        ),
+        (
+            SubscriptionType::StreamOnline,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                reward_id: Some("reward-1".to_string()),
+                ..Default::default()
+            },
+            "unexpected reward_id",
+        ),
+        (
+            SubscriptionType::ChannelRaid,
+            EventSubCondition {
+                from_broadcaster_user_id: Some("1000".to_string()),
+                to_broadcaster_user_id: Some("1001".to_string()),
+                ..Default::default()
+            },
+            "expected exactly one of from_broadcaster_user_id, to_broadcaster_user_id",
+        ),
+    ];
+    for (subscription_type, condition, expected) in invalid {
+        let create = EventSubCreate::new(
+            subscription_type,
+            condition,
+            EventSubTransport::webhook(
+                "https://example.com/eventsub".to_string(),
+                "secretsecret".to_string(),
+            ),
+        );
+        match client.create_eventsub_subscription(&create).await {
+            Err(Error::InvalidCondition { message, .. }) => assert_eq!(message, expected),
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+    assert!(server.fixtures().subscriptions.is_empty());
+}
+
+#[tokio::test]
+async fn conduit_shards_are_merged_into_one_stream() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let conduit = client.create_conduit(2).await.unwrap();
+    let (mut shards, _events) = client.connect_conduit(conduit.id.clone(), 2).await.unwrap();
+    assert_eq!(shards.len(), 2);
+
+    let enabled: Vec<_> = client
+        .get_conduit_shards(conduit.id.clone(), Some("enabled".to_string()), None)
+        .await
+        .unwrap()
+        .data;
+    assert_eq!(enabled.len(), 2);
+    let mut connected: Vec<_> = enabled
+        .iter()
+        .map(|s| s.transport.session_id.clone().unwrap())
+        .collect();
+    let mut session_ids = shards.session_ids();
+    connected.sort();
+    session_ids.sort();
+    assert_eq!(connected, session_ids);
+
+    client
+        .create_eventsub_subscription(&EventSubCreate::new(
+            SubscriptionType::StreamOffline,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+            EventSubTransport::conduit(conduit.id.clone()),
+        ))
+        .await
+        .unwrap();
+
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+    });
+    assert_eq!(server.send_notification("stream.offline", event), 1);
+    assert!(matches!(
+        shards.next().await,
+        Some(NotificationType::StreamOffline(..))
+    ));
+
+    let err = client
+        .connect_conduit("missing".to_string(), 1)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Twitch(e) if e.status == 404));
+}
+
+#[tokio::test]
+async fn dropped_conduit_shards_are_reassigned() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let conduit = client.create_conduit(1).await.unwrap();
+    let (mut shards, mut events) = client.connect_conduit(conduit.id.clone(), 1).await.unwrap();
+    let old_session = shards.session_ids()[0].clone();
+
+    client
+        .create_eventsub_subscription(&EventSubCreate::new(
+            SubscriptionType::StreamOffline,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+            EventSubTransport::conduit(conduit.id.clone()),
+        ))
+        .await
+        .unwrap();
+
+    assert!(server.close_session(&old_session, 4000, "Internal server error"));
+    let next = tokio::spawn(async move {
+        let event = shards.next().await;
+        (shards, event)
+    });
+
+    let new_session = loop {
+        match events.recv().await {
+            Some(ReconnectEvent::Reconnected { session_id }) => break session_id,
+            Some(ReconnectEvent::GaveUp { .. }) | None => panic!("Shard was not reassigned"),
+            Some(..) => {}
+        }
+    };
+    assert_ne!(new_session, old_session);
+
+    let shard = server.fixtures().conduit_shards[&conduit.id][0].clone();
+    assert_eq!(shard.status, "enabled");
+    assert_eq!(shard.transport.session_id, Some(new_session.clone()));
+
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+    });
+    assert_eq!(server.send_notification("stream.offline", event), 1);
+    let (shards, event) = next.await.unwrap();
+    assert!(matches!(event, Some(NotificationType::StreamOffline(..))));
+    assert_eq!(shards.session_ids(), vec![new_session]);
+}
+
+#[tokio::test]
+async fn stale_subscriptions_are_cleaned_up() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let live = client
+        .connect_eventsub(vec![
+            Subscription::stream_online("1000".to_string()),
+            Subscription::stream_online("1001".to_string()),
+        ])
+        .await
+        .unwrap();
+    let mut dead = client
+        .connect_eventsub(vec![Subscription::stream_offline("1000".to_string())])
+        .await
+        .unwrap();
+    assert!(server.close_session(&dead.session_id, 4000, "Internal server error"));
+    while dead.next().await.is_some() {}
+    for _ in 0..50 {
+        if server.fixtures().subscriptions[2].status != "enabled" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let page = client
+        .get_eventsub_subscriptions(
+            Some(EventSubFilter::Type("stream.online".to_string())),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.data.len(), 2);
+    assert_eq!((page.total, page.max_total_cost), (3, 10000));
+
+    let page = client
+        .get_eventsub_subscriptions(Some(EventSubFilter::UserId("1001".to_string())), None)
+        .await
+        .unwrap();
+    assert_eq!(page.data.len(), 1);
+
+    let page = client
+        .get_eventsub_subscriptions(
+            Some(EventSubFilter::Status("websocket_disconnected".to_string())),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.data[0].r#type, "stream.offline");
+
+    let pending = client
+        .create_eventsub_subscription(&EventSubCreate::new(
+            SubscriptionType::StreamOffline,
+            EventSubCondition {
+                broadcaster_user_id: Some("1001".to_string()),
+                ..Default::default()
+            },
+            EventSubTransport {
+                method: "webhook".to_string(),
+                callback: Some("https://example.com/eventsub".to_string()),
+                secret: Some("secretsecret".to_string()),
+                ..Default::default()
+            },
+        ))
+        .await
+        .unwrap();
+    server.fixtures().subscriptions[3].status = "webhook_callback_verification_pending".to_string();
+
+    let deleted = client.cleanup_eventsub_subscriptions(None).await.unwrap();
+    assert_eq!(deleted, 1);
+
+    //Without active sessions, enabled websocket subscriptions of other sessions are kept.
+    let other = client
+        .connect_eventsub(vec![Subscription::stream_offline("1000".to_string())])
+        .await
+        .unwrap();
+    let deleted = client.cleanup_eventsub_subscriptions(None).await.unwrap();
+    assert_eq!(deleted, 0);
+
+    let deleted = client
+        .cleanup_eventsub_subscriptions(Some(std::slice::from_ref(&live.session_id)))
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    drop(other);
+    let remaining: Vec<_> = server
+        .fixtures()
+        .subscriptions
+        .iter()
+        .map(|s| s.id.clone())
+        .collect();
+    assert_eq!(remaining.len(), 3);
+    assert!(remaining.contains(&pending.id));
+}
+
+#[tokio::test]
+async fn channel_events_are_typed() {
+    let fixtures = Fixtures {
+        scopes: vec!["channel:read:polls".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![
+            Subscription::channel_raid_to("1000".to_string()),
+            Subscription::new(
+                SubscriptionType::ChannelPollEnd,
+                EventSubCondition {
+                    broadcaster_user_id: Some("1000".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ])
+        .await
+        .unwrap();
+
+    let event = json!({
+        "from_broadcaster_user_id": "1001",
+        "from_broadcaster_user_login": "raider",
+        "from_broadcaster_user_name": "Raider",
+        "to_broadcaster_user_id": "1000",
+        "to_broadcaster_user_login": "mock_user",
+        "to_broadcaster_user_name": "Mock_User",
+        "viewers": 42,
+    });
+    assert_eq!(server.send_notification("channel.raid", event), 1);
+    match eventsub.next().await {
+        Some(NotificationType::ChannelRaid(raid)) => {
+            assert_eq!(
+                (raid.from_broadcaster_user_login.as_str(), raid.viewers),
+                ("raider", 42)
+            )
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+
+    let event = json!({
+        "id": "poll-1",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "title": "Best language?",
+        "choices": [
+            {"id": "a", "title": "Rust", "bits_votes": 0, "channel_points_votes": 10, "votes": 12},
+            {"id": "b", "title": "Go", "bits_votes": 0, "channel_points_votes": 0, "votes": 1},
+        ],
+        "bits_voting": {"is_enabled": false, "amount_per_vote": 0},
+        "channel_points_voting": {"is_enabled": true, "amount_per_vote": 10},
+        "status": "completed",
+        "started_at": "2023-01-01T00:00:00Z",
+        "ended_at": "2023-01-01T00:05:00Z",
+    });
+    assert_eq!(server.send_notification("channel.poll.end", event), 1);
+    match eventsub.next().await {
+        Some(NotificationType::ChannelPollEnd(poll)) => {
+            assert_eq!(poll.status.as_deref(), Some("completed"));
+            assert_eq!(poll.choices[0].votes, Some(12));
+            assert_eq!(poll.ends_at, None);
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn chat_mode_yields_structured_chat_events() {
+    let fixtures = Fixtures {
+        scopes: vec!["user:read:chat".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let mut subscriptions = Subscription::chat("1000".to_string(), "1000".to_string());
+    subscriptions.push(Subscription::stream_online("1000".to_string()));
+    let mut chat = client
+        .connect_eventsub(subscriptions)
+        .await
+        .unwrap()
+        .into_chat();
+    assert_eq!(server.fixtures().subscriptions.len(), 7);
+
+    let event = json!({
+        "id": "9001",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "type": "live",
+        "started_at": "2023-01-01T00:00:00Z",
+    });
+    assert_eq!(server.send_notification("stream.online", event), 1);
+
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "chatter_user_id": "1001",
+        "chatter_user_login": "viewer",
+        "chatter_user_name": "Viewer",
+        "message_id": "msg-1",
+        "message": {
+            "text": "@Mock_User Kappa hi",
+            "fragments": [
+                {"type": "mention", "text": "@Mock_User", "cheermote": null, "emote": null,
+                 "mention": {"user_id": "1000", "user_name": "Mock_User", "user_login": "mock_user"}},
+                {"type": "text", "text": " ", "cheermote": null, "emote": null, "mention": null},
+                {"type": "emote", "text": "Kappa", "cheermote": null, "mention": null,
+                 "emote": {"id": "25", "emote_set_id": "0", "owner_id": "0", "format": ["static"]}},
+                {"type": "text", "text": " hi", "cheermote": null, "emote": null, "mention": null},
+            ],
+        },
+        "message_type": "text",
+        "badges": [{"set_id": "subscriber", "id": "12", "info": "16"}],
+        "cheer": null,
+        "color": "#00FF7F",
+        "reply": {
+            "parent_message_id": "msg-0",
+            "parent_message_body": "hello",
+            "parent_user_id": "1000",
+            "parent_user_name": "Mock_User",
+            "parent_user_login": "mock_user",
+            "thread_message_id": "msg-0",
+            "thread_user_id": "1000",
+            "thread_user_name": "Mock_User",
+            "thread_user_login": "mock_user",
+        },
+        "channel_points_custom_reward_id": null,
+    });
+    assert_eq!(server.send_notification("channel.chat.message", event), 1);
+
+    match chat.next().await {
+        Some(ChatEvent::Message(message)) => {
+            assert_eq!(message.chatter_user_login, "viewer");
+            assert_eq!(message.badges[0].set_id, "subscriber");
+            assert_eq!(message.reply.unwrap().parent_message_id, "msg-0");
+            assert!(matches!(
+                &message.message.fragments[0],
+                ChatMessageFragment::Mention { mention, .. } if mention.user_id == "1000"
+            ));
+            assert!(matches!(
+                &message.message.fragments[2],
+                ChatMessageFragment::Emote { emote, .. } if emote.id == "25"
+            ));
+        }
+        other => panic!("Unexpected chat event: {other:?}"),
+    }
+
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "chatter_user_id": "1002",
+        "chatter_user_login": "raider",
+        "chatter_user_name": "Raider",
+        "chatter_is_anonymous": false,
+        "color": "",
+        "badges": [],
+        "system_message": "42 raiders from Raider have joined!",
+        "message_id": "msg-2",
+        "message": {"text": "", "fragments": []},
+        "notice_type": "raid",
+        "raid": {
+            "user_id": "1002",
+            "user_name": "Raider",
+            "user_login": "raider",
+            "viewer_count": 42,
+            "profile_image_url": "https://example.com/raider.png",
+        },
+    });
+    assert_eq!(
+        server.send_notification("channel.chat.notification", event),
+        1
+    );
+
+    match chat.next().await {
+        Some(ChatEvent::Notification(notification)) => {
+            assert_eq!(notification.notice_type, "raid");
+            assert_eq!(notification.raid.unwrap().viewer_count, 42);
+            assert!(notification.sub.is_none());
+        }
+        other => panic!("Unexpected chat event: {other:?}"),
+    }
+
+    let id = server
+        .fixtures()
+        .subscriptions
+        .iter()
+        .find(|s| s.r#type == "channel.chat.message")
+        .unwrap()
+        .id
+        .clone();
+    assert!(server.send_revocation(&id, "authorization_revoked"));
+    match chat.next().await {
+        Some(ChatEvent::Revocation(revocation)) => {
+            assert_eq!(revocation.subscription.id, id);
+            assert_eq!(revocation.subscription.status, "authorization_revoked");
+        }
+        other => panic!("Unexpected chat event: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn redemption_and_reward_updates_are_typed() {
+    let fixtures = Fixtures {
+        scopes: vec!["channel:read:redemptions".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![
+            Subscription::custom_reward_redemption_update("1000".to_string(), None),
+            Subscription::custom_reward_update("1000".to_string(), None),
+        ])
+        .await
+        .unwrap();
+
+    let event = json!({
+        "id": "redemption-1",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "user_id": "1001",
+        "user_login": "viewer",
+        "user_name": "Viewer",
+        "user_input": "",
+        "status": "fulfilled",
+        "reward": {"id": "reward-1", "title": "Hydrate", "cost": 100, "prompt": "Drink water"},
+        "redeemed_at": "2023-01-01T00:00:00Z",
+    });
+    assert_eq!(
+        server.send_notification(
+            "channel.channel_points_custom_reward_redemption.update",
+            event
+        ),
+        1
+    );
+    match eventsub.next().await {
+        Some(NotificationType::CustomRewardRedemptionUpdate(redemption)) => {
+            assert_eq!(redemption.status, "fulfilled");
+            assert_eq!(redemption.user_id, "1001");
+            assert_eq!(redemption.reward.cost, 100);
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+
+    let event = json!({
+        "id": "reward-1",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "is_enabled": true,
+        "is_paused": true,
+        "is_in_stock": true,
+        "title": "Hydrate",
+        "cost": 100,
+        "prompt": "Drink water",
+        "is_user_input_required": false,
+        "should_redemptions_skip_request_queue": false,
+        "cooldown_expires_at": null,
+        "redemptions_redeemed_current_stream": null,
+        "max_per_stream": {"is_enabled": false, "value": 0},
+        "max_per_user_per_stream": {"is_enabled": true, "value": 1},
+        "global_cooldown": {"is_enabled": false, "seconds": 0},
+        "background_color": "#00E5CB",
+        "image": null,
+        "default_image": {
+            "url_1x": "https://example.com/1.png",
+            "url_2x": "https://example.com/2.png",
+            "url_4x": "https://example.com/4.png",
+        },
+    });
+    assert_eq!(
+        server.send_notification("channel.channel_points_custom_reward.update", event),
+        1
+    );
+    match eventsub.next().await {
+        Some(NotificationType::CustomRewardUpdate(reward)) => {
+            assert!(reward.is_paused);
+            assert_eq!(reward.max_per_user_per_stream.value, 1);
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn notifications_carry_their_metadata() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut events = client
+        .connect_eventsub(vec![Subscription::stream_offline("1000".to_string())])
+        .await
+        .unwrap()
+        .into_events();
+    assert!(matches!(
+        events.next().await,
+        Some(SessionEvent::Welcome(..))
+    ));
+
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+    });
+    assert_eq!(server.send_notification("stream.offline", event), 1);
+
+    let envelope = match events.next().await {
+        Some(SessionEvent::Notification(v)) => v,
+        other => panic!("Unexpected event: {other:?}"),
+    };
+    let subscription = server.fixtures().subscriptions[0].clone();
+    assert_eq!(envelope.subscription.id, subscription.id);
+    assert_eq!(
+        envelope
+            .subscription
+            .condition
+            .broadcaster_user_id
+            .as_deref(),
+        Some("1000")
+    );
+    assert_eq!(envelope.metadata.subscription_version.as_deref(), Some("1"));
+    assert!(!envelope.metadata.message_id.is_empty());
+    assert!(
+        (chrono::Utc::now() - envelope.timestamp)
+            .num_seconds()
+            .abs()
+            < 5
+    );
+    assert!(matches!(
+        envelope.event,
+        NotificationType::StreamOffline(..)
+    ));
+}
+
+#[tokio::test]
+async fn envelope_streams_keep_metadata() {
+    let fixtures = Fixtures {
+        scopes: vec!["user:read:chat".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+    let event = json!({
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+    });
+
+    let eventsub = client
+        .connect_eventsub(vec![Subscription::stream_offline("1000".to_string())])
+        .await
+        .unwrap();
+    let session_id = eventsub.session_id.clone();
+    let mut envelopes = eventsub.into_envelopes();
+    assert_eq!(server.send_notification("stream.offline", event.clone()), 1);
+    match envelopes.next().await {
+        Some(Ok(envelope)) => {
+            assert_eq!(envelope.subscription.r#type, "stream.offline");
+            assert!(matches!(
+                envelope.event,
+                NotificationType::StreamOffline(..)
+            ));
+        }
+        other => panic!("Unexpected envelope: {other:?}"),
+    }
+    assert!(server.send_message(
+        &session_id,
+        tokio_tungstenite::tungstenite::Message::Text("not json".to_string())
+    ));
+    assert!(matches!(
+        envelopes.next().await,
+        Some(Err(malformed)) if malformed.payload == json!("not json")
+    ));
+    drop(envelopes);
+    server.fixtures().subscriptions.clear();
+
+    let mut subscriptions = Subscription::chat("1000".to_string(), "1000".to_string());
+    subscriptions.push(Subscription::stream_offline("1000".to_string()));
+    let mut chat = client
+        .connect_eventsub(subscriptions)
+        .await
+        .unwrap()
+        .into_chat_envelopes();
+    assert_eq!(server.send_notification("stream.offline", event.clone()), 1);
+    assert_eq!(
+        server.send_notification("channel.chat.clear", event.clone()),
+        1
+    );
+    match chat.next().await {
+        Some(Ok(envelope)) => {
+            assert_eq!(envelope.subscription.r#type, "channel.chat.clear");
+            assert_eq!(envelope.metadata.subscription_version.as_deref(), Some("1"));
+            assert!(matches!(envelope.event, ChatEvent::Clear(..)));
+        }
+        other => panic!("Unexpected chat envelope: {other:?}"),
+    }
+    drop(chat);
+    server.fixtures().subscriptions.clear();
+
+    let conduit = client.create_conduit(1).await.unwrap();
+    let (shards, _events) = client.connect_conduit(conduit.id.clone(), 1).await.unwrap();
+    let mut shards = shards.into_envelopes();
+    client
+        .create_eventsub_subscription(&EventSubCreate::new(
+            SubscriptionType::StreamOffline,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+            EventSubTransport::conduit(conduit.id.clone()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(server.send_notification("stream.offline", event), 1);
+    match shards.next().await {
+        Some(Ok(envelope)) => {
+            assert_eq!(envelope.subscription.transport.conduit_id, Some(conduit.id));
+            assert!(matches!(
+                envelope.event,
+                NotificationType::StreamOffline(..)
+            ));
+        }
+        other => panic!("Unexpected envelope: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn malformed_messages_do_not_end_the_stream() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let mut eventsub = client
+        .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+    let session_id = eventsub.session_id.clone();
+
+    //A schema change on Twitch's side, started_at is no longer a string.
+    let event = json!({
+        "id": "9001",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "type": "live",
+        "started_at": 1672531200,
+    });
+    assert_eq!(server.send_notification("stream.online", event), 1);
+    match eventsub.next().await {
+        Some(NotificationType::Malformed(malformed)) => {
+            assert_eq!(malformed.payload["event"]["id"], "9001");
+            assert!(malformed.error.contains("expected a string"));
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+
+    assert!(server.send_message(
+        &session_id,
+        tokio_tungstenite::tungstenite::Message::Text("not json".to_string())
+    ));
+    match eventsub.next().await {
+        Some(NotificationType::Malformed(malformed)) => {
+            assert_eq!(malformed.payload, json!("not json"))
+        }
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+
+    let event = json!({
+        "id": "9002",
+        "broadcaster_user_id": "1000",
+        "broadcaster_user_login": "mock_user",
+        "broadcaster_user_name": "Mock_User",
+        "type": "live",
+        "started_at": "2023-01-01T00:00:00Z",
+    });
+    assert_eq!(server.send_notification("stream.online", event), 1);
+    match eventsub.next().await {
+        Some(NotificationType::StreamOnline(event)) => assert_eq!(event.id, "9002"),
+        other => panic!("Unexpected notification: {other:?}"),
+    }
+    assert_eq!(eventsub.session_id, session_id);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn closing_sessions_while_subscribing_does_not_deadlock() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    //A lock order inversion in the mock blocks the runtime's worker threads, so this hangs
+    //rather than fails when it regresses.
+    for _ in 0..20 {
+        let mut eventsub = client
+            .connect_eventsub(vec![Subscription::stream_online("1000".to_string())])
+            .await
+            .unwrap();
+        let session_id = eventsub.session_id.clone();
+
+        let subscribe = {
+            let client = client.clone();
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                let subscription = EventSubCreate::new(
+                    SubscriptionType::StreamOffline,
+                    EventSubCondition {
+                        broadcaster_user_id: Some("1000".to_string()),
+                        ..Default::default()
+                    },
+                    EventSubTransport::websocket(session_id),
+                );
+                let _ = client.create_eventsub_subscription(&subscription).await;
+            })
+        };
+        server.close_session(&session_id, 4000, "Internal server error");
+        while eventsub.next().await.is_some() {}
+        subscribe.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn resilient_client_gives_up_on_permanent_errors() {
+    let fixtures = Fixtures {
+        scopes: vec!["channel:read:polls".to_string()],
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let (mut eventsub, mut events) = client
+        .connect_eventsub_resilient(vec![Subscription::new(
+            SubscriptionType::ChannelPollBegin,
+            EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+        )])
+        .await
+        .unwrap();
+
+    //The token lost its scope while disconnected, resubscribing can't succeed.
+    server.fixtures().scopes.clear();
+    assert!(server.close_session(&eventsub.session_id, 4000, "Internal server error"));
+    assert!(eventsub.next().await.is_none());
+    match &eventsub.last_error {
+        Some(Error::Twitch(e)) => assert_eq!(e.status, 403),
+        other => panic!("Unexpected error: {other:?}"),
+    }
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::Disconnected(..))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::Reconnecting { attempt: 1, .. })
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::GaveUp { attempt: 1 })
+    ));
+}
+
+#[tokio::test]
+async fn resilient_backoff_is_not_reset_while_the_server_is_down() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let (mut eventsub, mut events) = client
+        .connect_eventsub_resilient(vec![Subscription::stream_online("1000".to_string())])
+        .await
+        .unwrap();
+
+    //Paused time lets the backoff run past the 30 second ping interval without waiting for it.
+    tokio::time::pause();
+    server.fixtures().eventsub_unavailable = true;
+    assert!(server.close_session(&eventsub.session_id, 4000, "Internal server error"));
+    let next = tokio::spawn(async move {
+        let event = eventsub.next().await;
+        (eventsub, event)
+    });
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ReconnectEvent::Disconnected(..))
+    ));
+    //Attempt 7 waits 32 seconds, so the first six already took longer than a ping interval.
+    let mut expected = 1;
+    while expected <= 7 {
+        match events.recv().await {
+            Some(ReconnectEvent::Reconnecting { attempt, .. }) => {
+                assert_eq!(attempt, expected);
+                expected += 1;
+            }
+            Some(ReconnectEvent::Failed { attempt, .. }) => assert_eq!(attempt, expected - 1),
+            other => panic!("Unexpected event: {other:?}"),
+        }
+    }
+    next.abort();
+}
+
+#[test]
+fn subscriptions_are_built_from_the_catalogue() {
+    let subscription = Subscription::from_ids(
+        SubscriptionType::ChannelChatMessage,
+        vec!["1000".to_string(), "1001".to_string()],
+    )
+    .unwrap();
+    assert_eq!(subscription.r#type, "channel.chat.message");
+    assert_eq!(subscription.version, "1");
+    assert_eq!(
+        subscription.condition.broadcaster_user_id.as_deref(),
+        Some("1000")
+    );
+    assert_eq!(subscription.condition.user_id.as_deref(), Some("1001"));
+
+    for subscription_type in SubscriptionType::ALL {
+        let ids = subscription_type
+            .condition_fields()
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        let result = Subscription::from_ids(*subscription_type, ids);
+        //Only channel.raid has no required fields and needs one of its optional ones.
+        assert_eq!(
+            result.is_ok(),
+            *subscription_type != SubscriptionType::ChannelRaid,
+            "{subscription_type}"
+        );
+    }
+
+    assert!(matches!(
+        Subscription::from_ids(SubscriptionType::ChannelFollow, vec!["1000".to_string()]),
+        Err(Error::InvalidCondition { .. })
+    ));
+}
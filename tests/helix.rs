@@ -0,0 +1,247 @@
+use futures::TryStreamExt;
+use tw_api::auth::{Token, TokenType};
+use tw_api::helix::{EventSubCondition, EventSubCreate, EventSubTransport, RewardCreate};
+use tw_api::mock::{Fixtures, MockServer};
+use tw_api::Error;
+
+#[tokio::test]
+async fn get_user_returns_token_user() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let user = client.get_user().await.unwrap();
+    assert_eq!(user.id, "1000");
+    assert_eq!(client.get_token_user_login().await.unwrap(), "mock_user");
+}
+
+#[tokio::test]
+async fn paginate_streams_follows_cursors() {
+    let fixtures = Fixtures {
+        streams: (0..7)
+            .map(|i| Fixtures::stream(&Fixtures::user(&format!("{i}"), &format!("user{i}"))))
+            .collect(),
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let page = client
+        .get_streams_page(None, None, None, None, None, Some(3), None, None)
+        .await
+        .unwrap();
+    assert_eq!(page.data.len(), 3);
+    assert_eq!(page.pagination.cursor.as_deref(), Some("3"));
+
+    let streams = client
+        .get_streams(
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(3),
+            None,
+            page.pagination.cursor,
+        )
+        .await
+        .unwrap();
+    assert_eq!(streams.len(), 3);
+
+    let streams: Vec<_> = client
+        .paginate_streams(None, None, None, None, None, Some(3), None)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streams.len(), 7);
+
+    let streams: Vec<_> = client
+        .paginate_streams(None, None, None, None, None, Some(3), Some(5))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streams.len(), 5);
+}
+
+#[tokio::test]
+async fn expired_token_is_refreshed_once() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+    server.fixtures().access_token = "rotated".to_string();
+
+    let requests = (0..8).map(|_| {
+        let client = client.clone();
+        tokio::spawn(async move { client.get_user().await })
+    });
+    for request in requests {
+        request.await.unwrap().unwrap();
+    }
+
+    assert_eq!(server.fixtures().token_requests, 1);
+    assert_eq!(
+        client.token.read().await.access_token,
+        server.fixtures().access_token
+    );
+}
+
+#[tokio::test]
+async fn twitch_errors_are_structured() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    match client.delete_custom_reward("missing".to_string()).await {
+        Err(Error::Twitch(e)) => assert_eq!(e.status, 404),
+        r => panic!("unexpected result: {r:?}"),
+    }
+
+    let reward = RewardCreate {
+        title: "Hydrate".to_string(),
+        cost: 10,
+        ..Default::default()
+    };
+    match client.create_custom_reward(&reward).await {
+        Err(Error::Twitch(e)) => assert_eq!(e.status, 400),
+        r => panic!("unexpected result: {r:?}"),
+    }
+}
+
+#[tokio::test]
+async fn custom_rewards_round_trip() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let reward = client
+        .create_custom_reward(&RewardCreate {
+            title: "Stretch".to_string(),
+            cost: 50,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.get_custom_rewards(vec![]).await.unwrap().len(), 2);
+
+    client
+        .delete_custom_reward(reward.id.clone())
+        .await
+        .unwrap();
+    assert!(client.get_custom_reward(reward.id).await.is_err());
+}
+
+#[tokio::test]
+async fn eventsub_subscriptions_are_recorded() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+
+    let subscription = client
+        .create_eventsub_subscription(&EventSubCreate {
+            r#type: "stream.online".to_string(),
+            version: "1".to_string(),
+            condition: EventSubCondition {
+                broadcaster_user_id: Some("1000".to_string()),
+                ..Default::default()
+            },
+            transport: EventSubTransport {
+                method: "webhook".to_string(),
+                callback: Some("https://example.com/eventsub".to_string()),
+                secret: Some("secretsecret".to_string()),
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+    assert_eq!(server.fixtures().subscriptions.len(), 1);
+
+    client
+        .delete_eventsub_subscription(subscription.id)
+        .await
+        .unwrap();
+    assert!(server.fixtures().subscriptions.is_empty());
+}
+
+#[tokio::test]
+async fn rate_limited_requests_are_retried() {
+    let fixtures = Fixtures {
+        rate_limited_requests: 1,
+        ..Default::default()
+    };
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+    assert!(client.rate_limit().is_none());
+
+    let user = client.get_user().await.unwrap();
+    assert_eq!(user.id, "1000");
+    assert_eq!(server.fixtures().rate_limited_requests, 0);
+
+    let rate_limit = client.rate_limit().unwrap();
+    assert_eq!(rate_limit.limit, 800);
+    assert_eq!(rate_limit.remaining, 798);
+    assert!(rate_limit.reset > chrono::Utc::now());
+}
+
+#[tokio::test]
+async fn channel_followers_are_paginated() {
+    let mut fixtures = Fixtures::default();
+    fixtures.followers.insert(
+        "1000".to_string(),
+        (0..5)
+            .map(|i| {
+                Fixtures::follower(
+                    &Fixtures::user(&format!("{i}"), &format!("user{i}")),
+                    "2021-01-01T00:00:00Z",
+                )
+            })
+            .collect(),
+    );
+    let server = MockServer::with_fixtures(fixtures).await.unwrap();
+    let client = server.client();
+
+    let page = client
+        .get_channel_followers("1000".to_string(), None, Some(2), None)
+        .await
+        .unwrap();
+    assert_eq!(page.data.len(), 2);
+    assert_eq!(page.total, 5);
+    assert_eq!(page.pagination.cursor.as_deref(), Some("2"));
+
+    let page = client
+        .get_channel_followers("1000".to_string(), Some("3".to_string()), None, None)
+        .await
+        .unwrap();
+    assert_eq!(page.data[0].user_login, "user3");
+
+    let followers: Vec<_> = client
+        .paginate_channel_followers("1000".to_string(), None, Some(2), None)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(followers.len(), 5);
+    assert_eq!(
+        client
+            .get_channel_followers_total("1001".to_string())
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn app_token_requests_use_builder_settings() {
+    let server = MockServer::start().await.unwrap();
+    let client = server
+        .client_builder()
+        .token(Token {
+            token_type: TokenType::AppAccessToken,
+            ..Default::default()
+        })
+        .user_agent("tw-api-test".to_string())
+        .build();
+
+    client.refresh_token().await.unwrap();
+    assert_eq!(
+        client.token.read().await.access_token,
+        server.fixtures().access_token
+    );
+    assert_eq!(
+        server.fixtures().token_user_agent.as_deref(),
+        Some("tw-api-test")
+    );
+}
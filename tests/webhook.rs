@@ -0,0 +1,186 @@
+use serde_json::json;
+use tw_api::eventsub::{Envelope, NotificationType};
+use tw_api::webhook::Webhook;
+
+use http::{HeaderMap, StatusCode};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+fn request(
+    webhook: &Webhook,
+    id: &str,
+    message_type: &str,
+    body: &serde_json::Value,
+) -> (HeaderMap, Vec<u8>) {
+    request_at(webhook, id, message_type, body, chrono::Utc::now())
+}
+
+fn request_at(
+    webhook: &Webhook,
+    id: &str,
+    message_type: &str,
+    body: &serde_json::Value,
+    timestamp: chrono::DateTime<chrono::Utc>,
+) -> (HeaderMap, Vec<u8>) {
+    let body = body.to_string().into_bytes();
+    let timestamp = timestamp.to_rfc3339();
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Twitch-Eventsub-Message-Id", id.parse().unwrap());
+    headers.insert(
+        "Twitch-Eventsub-Message-Timestamp",
+        timestamp.parse().unwrap(),
+    );
+    headers.insert(
+        "Twitch-Eventsub-Message-Signature",
+        webhook.signature(id, &timestamp, &body).parse().unwrap(),
+    );
+    headers.insert(
+        "Twitch-Eventsub-Message-Type",
+        message_type.parse().unwrap(),
+    );
+    headers.insert(
+        "Twitch-Eventsub-Subscription-Type",
+        "stream.offline".parse().unwrap(),
+    );
+    (headers, body)
+}
+
+async fn received(mut receiver: UnboundedReceiver<Envelope>) -> Vec<Envelope> {
+    let mut events = Vec::new();
+    while let Some(v) = receiver.recv().await {
+        events.push(v);
+    }
+    events
+}
+
+fn subscription() -> serde_json::Value {
+    json!({
+        "id": "sub-1",
+        "status": "enabled",
+        "type": "stream.offline",
+        "version": "1",
+        "condition": { "broadcaster_user_id": "1000" },
+        "created_at": "2023-01-01T00:00:00Z",
+        "transport": { "method": "webhook", "callback": "https://example.com/eventsub" },
+        "cost": 0,
+    })
+}
+
+#[tokio::test]
+async fn webhook_answers_challenges_and_forwards_notifications() {
+    let (webhook, receiver) = Webhook::new("secretsecret".to_string());
+
+    let body = json!({ "challenge": "pogchamp-kappa-360noscope", "subscription": subscription() });
+    let (headers, body) = request(&webhook, "m1", "webhook_callback_verification", &body);
+    assert_eq!(
+        webhook.respond(&headers, &body),
+        (StatusCode::OK, "pogchamp-kappa-360noscope".to_string())
+    );
+
+    let body = json!({
+        "subscription": subscription(),
+        "event": {
+            "broadcaster_user_id": "1000",
+            "broadcaster_user_login": "mock_user",
+            "broadcaster_user_name": "Mock_User",
+        },
+    });
+    let (headers, body) = request(&webhook, "m2", "notification", &body);
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::NO_CONTENT);
+    //Replays are acknowledged without being forwarded again.
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::NO_CONTENT);
+
+    let body = json!({ "subscription": subscription() });
+    let (headers, body) = request(&webhook, "m3", "revocation", &body);
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::NO_CONTENT);
+
+    drop(webhook);
+    let events = received(receiver).await;
+    assert_eq!(events.len(), 2);
+    assert!(
+        matches!(&events[0].event, NotificationType::StreamOffline(e) if e.broadcaster_user_id == "1000")
+    );
+    assert_eq!(events[0].metadata.message_id, "m2");
+    assert_eq!(events[0].subscription.id, "sub-1");
+    assert!(
+        matches!(&events[1].event, NotificationType::Revocation(r) if r.subscription.id == "sub-1")
+    );
+}
+
+#[tokio::test]
+async fn webhook_rejects_bad_signatures() {
+    let (webhook, _receiver) = Webhook::new("secretsecret".to_string());
+    let (other, _) = Webhook::new("othersecret".to_string());
+
+    let body = json!({ "challenge": "abc", "subscription": subscription() });
+    let (headers, body) = request(&other, "m1", "webhook_callback_verification", &body);
+    assert!(webhook.verify(&headers, &body).is_err());
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::FORBIDDEN);
+
+    let (headers, mut body) = request(&webhook, "m2", "webhook_callback_verification", &json!({}));
+    body.push(b' ');
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn webhook_only_deduplicates_accepted_notifications() {
+    let (webhook, receiver) = Webhook::new("secretsecret".to_string());
+
+    //Twitch resends the challenge with the same id if the first answer got lost.
+    let body = json!({ "challenge": "abc", "subscription": subscription() });
+    let (headers, body) = request(&webhook, "m1", "webhook_callback_verification", &body);
+    for _ in 0..2 {
+        assert_eq!(
+            webhook.respond(&headers, &body),
+            (StatusCode::OK, "abc".to_string())
+        );
+    }
+
+    let (headers, body) = request(&webhook, "m2", "notification", &json!("not a notification"));
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::BAD_REQUEST);
+
+    let body = json!({
+        "subscription": subscription(),
+        "event": {
+            "broadcaster_user_id": "1000",
+            "broadcaster_user_login": "mock_user",
+            "broadcaster_user_name": "Mock_User",
+        },
+    });
+    let (headers, body) = request(&webhook, "m2", "notification", &body);
+    assert_eq!(webhook.respond(&headers, &body).0, StatusCode::NO_CONTENT);
+
+    drop(webhook);
+    let events = received(receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].metadata.message_id, "m2");
+}
+
+#[tokio::test]
+async fn webhook_rejects_stale_messages_and_serves_http_requests() {
+    let (webhook, receiver) = Webhook::new("secretsecret".to_string());
+    let body = json!({
+        "subscription": subscription(),
+        "event": {
+            "broadcaster_user_id": "1000",
+            "broadcaster_user_login": "mock_user",
+            "broadcaster_user_name": "Mock_User",
+        },
+    });
+
+    //Unlike a duplicate, a message that is too old is not acknowledged.
+    let stale = chrono::Utc::now() - chrono::Duration::minutes(11);
+    let (headers, bytes) = request_at(&webhook, "m1", "notification", &body, stale);
+    assert_eq!(webhook.respond(&headers, &bytes).0, StatusCode::BAD_REQUEST);
+
+    let (headers, bytes) = request(&webhook, "m2", "notification", &body);
+    let mut request = http::Request::new(bytes);
+    *request.headers_mut() = headers;
+    let response = webhook.handle(request);
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    drop(webhook);
+    let events = received(receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].metadata.message_id, "m2");
+}